@@ -1,37 +1,237 @@
-use crate::{CommandReturn, Config, Severity, Workspace, WorkspaceError, Workspaces};
-use std::{io, io::Write, path::PathBuf};
+use crate::{
+    filter::Filter,
+    is_valid_tag, local,
+    output::{self, say},
+    patch::Patch,
+    paths, picker, registry, timestamps, wizard, CommandReturn, Config, EnvVar, ErrorCode,
+    FileConfig, ListFormat, ListSort, ModifyArgs, Profile, Registry, RegistryLock, Severity, Shell,
+    Workspace, WorkspaceError, WorkspaceSource, Workspaces, DEFAULT_PROFILE,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 use text_io::scan;
 
 // Command functions
-pub fn init(workspaces: Workspaces, name: String) -> CommandReturn {
-    if let Some(workspace) = workspaces.workspaces.iter().find(|w| w.name == name) {
-        workspace.init().into()
+pub fn init(workspaces: Workspaces, name: Option<String>, tags: Vec<String>) -> CommandReturn {
+    let name = match name {
+        Some(name) => name,
+        None => {
+            let tagged: Vec<Workspace> = workspaces
+                .with_local()
+                .filter(|w| w.has_tags(&tags))
+                .cloned()
+                .collect();
+            if tagged.is_empty() && !tags.is_empty() {
+                return not_tagged(&tags);
+            }
+            if output::is_json() {
+                return WorkspaceError::coded(
+                    ErrorCode::InvalidArgument,
+                    "Name the workspace, it can't be picked with --json".to_string(),
+                )
+                .into();
+            }
+            return match picker::pick(&tagged) {
+                Ok(Some(workspace)) => launch(workspaces, workspace),
+                Ok(None) => WorkspaceError::new("No workspace selected".into(), Severity::Message)
+                    .with_code(ErrorCode::Cancelled)
+                    .into(),
+                Err(err) => err.into(),
+            };
+        }
+    };
+
+    if let Some(workspace) = workspaces.find(&name).cloned() {
+        launch(workspaces, workspace)
     } else {
-        "Workspace not found".into()
+        not_found()
     }
 }
 
+fn not_found() -> CommandReturn {
+    WorkspaceError::coded(ErrorCode::NotFound, "Workspace not found".to_string()).into()
+}
+
+fn not_tagged(tags: &[String]) -> CommandReturn {
+    WorkspaceError::coded(
+        ErrorCode::NotFound,
+        format!("No workspace is tagged {}", tags.join(", ")),
+    )
+    .into()
+}
+
+fn no_workspaces() -> CommandReturn {
+    WorkspaceError::coded(ErrorCode::Empty, "No workspaces".to_string()).into()
+}
+
+fn launch(mut workspaces: Workspaces, workspace: Workspace) -> CommandReturn {
+    // Failing to record usage shouldn't keep the workspace from opening
+    if let Err(err) = workspaces.record_launch(&workspace) {
+        output::warn(err);
+    }
+    let path = match workspaces.resolve(&workspace) {
+        Ok(path) => path,
+        Err(err) => return err.into(),
+    };
+    output::data(&json!({ "launched": workspace.name, "path": path }));
+    workspace.init(&path).into()
+}
+
+pub fn up(mut workspaces: Workspaces, tags: Vec<String>) -> CommandReturn {
+    match workspaces.up(&tags) {
+        Ok(started) if started.is_empty() && tags.is_empty() => no_workspaces(),
+        Ok(started) if started.is_empty() => not_tagged(&tags),
+        Ok(started) => {
+            let names: Vec<&str> = started.iter().map(|w| w.name.as_str()).collect();
+            output::data(&json!({ "started": names }));
+            say!("Brought up {} workspaces", started.len());
+            ().into()
+        }
+        Err(err) => err.into(),
+    }
+}
+
+pub fn jump(workspaces: Workspaces, query: Vec<String>) -> CommandReturn {
+    let candidates = crate::jump::rank(&query, &workspaces.workspaces, timestamps::now());
+    if candidates.is_empty() {
+        return WorkspaceError::coded(
+            ErrorCode::NotFound,
+            format!("No workspace matches {}", query.join(" ")),
+        )
+        .into();
+    }
+
+    if let Some(workspace) = crate::jump::best(&query, &candidates) {
+        let workspace = workspace.clone();
+        return launch(workspaces, workspace);
+    }
+
+    let ranked: Vec<_> = candidates
+        .iter()
+        .map(|c| json!({ "name": c.workspace.name, "path": c.workspace.path, "score": c.score }))
+        .collect();
+    output::data(&json!({ "candidates": ranked }));
+    for (rank, candidate) in candidates.iter().enumerate() {
+        say!(
+            "{}. {} ({}), score: {:.1}",
+            rank + 1,
+            candidate.workspace.name,
+            candidate.workspace.path.display(),
+            candidate.score
+        );
+    }
+    WorkspaceError::new(
+        format!(
+            "{} matches several workspaces, be more specific or use `ws run <name>`",
+            query.join(" ")
+        ),
+        Severity::Warning,
+    )
+    .with_code(ErrorCode::Ambiguous)
+    .into()
+}
+
 pub fn add(
     mut workspaces: Workspaces,
-    name: String,
-    path: PathBuf,
+    name: Option<String>,
+    path: Option<PathBuf>,
     shell_executable: Option<String>,
     text: Option<String>,
     cmd_path: Option<PathBuf>,
+    tags: Vec<String>,
 ) -> CommandReturn {
-    let shell = match shell_executable {
-        Some(shell) => shell,
-        None => {
-            if cfg!(target_os = "windows") {
-                return "cmd".into();
-            } else {
-                return "sh".into();
-            }
+    let guided = name.is_none();
+    let workspace = match (name, path) {
+        (Some(name), Some(path)) => {
+            new_workspace(name, path, shell_executable, text, cmd_path, tags).map(Some)
         }
+        _ => ask_workspace(&workspaces, shell_executable, tags),
     };
+    let workspace = match workspace {
+        Ok(Some(workspace)) => workspace,
+        Ok(None) => return cancelled("Workspace not added"),
+        Err(err) => return err,
+    };
+    let name = workspace.name.clone();
+    if let Err(err) = workspaces.add(workspace) {
+        return err.into();
+    }
+    workspace_data(&workspaces, &name);
+    if guided {
+        say!("Added workspace {}", name);
+    }
+    ().into()
+}
+
+/// Prompts for the workspace `ws add` was run without, which needs a terminal
+fn ask_workspace(
+    workspaces: &Workspaces,
+    shell_executable: Option<String>,
+    tags: Vec<String>,
+) -> Result<Option<Workspace>, CommandReturn> {
+    if output::is_json() || !io::stdin().is_terminal() {
+        return Err(invalid_argument(
+            "Give the name and path of the workspace, they're only asked for on a terminal"
+                .to_string(),
+        ));
+    }
+    if let Some(tag) = tags.iter().find(|tag| !is_valid_tag(tag)) {
+        return Err(invalid_tag(tag));
+    }
+
+    let current_dir = env::current_dir().map_err(|e| CommandReturn::from(e.to_string()))?;
+    let shell = shell_executable.unwrap_or_else(Shell::detect_name);
+    wizard::ask_workspace(
+        workspaces,
+        &current_dir,
+        &shell,
+        &tags,
+        &mut io::stdin().lock(),
+    )
+    .map_err(CommandReturn::from)
+}
 
-    if let Some(command_text) = text {
-        let workspace = Workspace::new(
+/// Sets the stored workspace called `name` as the result of the command
+fn workspace_data(workspaces: &Workspaces, name: &str) {
+    if let Some(workspace) = workspaces.find(name) {
+        let row = Row {
+            workspace,
+            resolved: workspaces.resolve(workspace),
+        };
+        output::data(&json_workspace(workspaces, &row));
+    }
+}
+
+fn new_workspace(
+    name: String,
+    path: PathBuf,
+    shell_executable: Option<String>,
+    text: Option<String>,
+    cmd_path: Option<PathBuf>,
+    tags: Vec<String>,
+) -> Result<Workspace, CommandReturn> {
+    if let Some(tag) = tags.iter().find(|tag| !is_valid_tag(tag)) {
+        return Err(invalid_tag(tag));
+    }
+
+    let shell = shell_executable.unwrap_or_else(|| Shell::default_name().to_string());
+    let path = match env::current_dir() {
+        Ok(current_dir) => paths::normalize(&path, &current_dir),
+        Err(err) => return Err(err.to_string().into()),
+    };
+
+    let workspace = if let Some(command_text) = text {
+        Workspace::new(
             name,
             path,
             shell,
@@ -41,42 +241,474 @@ pub fn add(
                 .iter()
                 .map(|x| x.to_string())
                 .collect::<Vec<String>>(),
-        );
-        workspaces.add(workspace).into()
+        )
     } else if let Some(command_path) = cmd_path {
-        let workspace = Workspace::new(
-            name,
-            path,
-            shell,
-            vec![command_path.to_str().unwrap().to_string()],
-        );
-        workspaces.add(workspace).into()
+        Workspace {
+            init_commands: vec![command_path.into_os_string()],
+            ..Workspace::new(name, path, shell, vec![])
+        }
     } else {
-        "You must provide a value for either text or file based init commands".into()
+        return Err(WorkspaceError::coded(
+            ErrorCode::InvalidArgument,
+            "You must provide a value for either text or file based init commands".to_string(),
+        )
+        .into());
+    };
+    Ok(Workspace { tags, ..workspace })
+}
+
+fn invalid_tag(tag: &str) -> CommandReturn {
+    invalid_argument(format!(
+        "Invalid tag {:?}, tags can't be empty or contain commas or spaces",
+        tag
+    ))
+}
+
+pub fn list(
+    workspaces: Workspaces,
+    filter: Filter,
+    sort: Option<ListSort>,
+    format: ListFormat,
+    names_only: bool,
+) -> CommandReturn {
+    let mut filter = filter;
+    if let Some(path) = filter.path.take() {
+        match env::current_dir() {
+            Ok(current_dir) => filter.path = Some(paths::normalize(&path, &current_dir)),
+            Err(err) => return err.to_string().into(),
+        }
+    }
+
+    for (first, _) in &workspaces.conflicts {
+        if let Some(conflict) = workspaces.conflict(&first.name) {
+            output::warn(conflict);
+        }
+    }
+
+    let mut listed: Vec<&Workspace> = workspaces.workspaces.iter().collect();
+    match sort {
+        Some(ListSort::Name) => listed.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some(ListSort::Recent) => listed.sort_by_key(|w| Reverse(w.last_used_at)),
+        Some(ListSort::Launches) => listed.sort_by_key(|w| Reverse(w.launch_count)),
+        Some(ListSort::Created) => listed.sort_by_key(|w| Reverse(w.created_at)),
+        Some(ListSort::Path) => listed.sort_by(|a, b| a.path.cmp(&b.path)),
+        Some(ListSort::Shell) => {
+            listed.sort_by(|a, b| a.shell.cmp(&b.shell).then_with(|| a.name.cmp(&b.name)))
+        }
+        None => {}
+    }
+    // The local workspace comes last unless it is also registered
+    listed.extend(
+        workspaces
+            .local_workspace
+            .iter()
+            .filter(|local| !workspaces.workspaces.contains(local)),
+    );
+
+    let rows: Vec<Row> = listed
+        .into_iter()
+        .map(|workspace| Row {
+            workspace,
+            resolved: workspaces.resolve(workspace),
+        })
+        .filter(|row| {
+            let resolved = row.resolved.as_deref().unwrap_or(&row.workspace.path);
+            filter.matches(row.workspace, resolved)
+        })
+        .collect();
+
+    // --json always gets the full entries, whatever the format
+    if output::is_json() {
+        let entries: Vec<JsonWorkspace> = rows
+            .iter()
+            .map(|row| json_workspace(&workspaces, row))
+            .collect();
+        output::data(&entries);
+        return ().into();
     }
+
+    if names_only {
+        for row in &rows {
+            println!("{}", row.workspace.name);
+        }
+        return ().into();
+    }
+
+    match format {
+        ListFormat::Json => print_json(&workspaces, &rows),
+        ListFormat::Tsv => {
+            print_tsv(&workspaces, &rows);
+            ().into()
+        }
+        _ if rows.is_empty() => no_workspaces(),
+        ListFormat::Table => {
+            print_table(&workspaces, &rows);
+            ().into()
+        }
+        ListFormat::Text => {
+            print_text(&workspaces, &rows);
+            ().into()
+        }
+    }
+}
+
+/// A workspace being listed, with its path resolved for this machine
+struct Row<'a> {
+    workspace: &'a Workspace,
+    resolved: Result<PathBuf, WorkspaceError>,
 }
 
-// TODO: Filter by name or other criteria
-pub fn list(workspaces: Workspaces) -> CommandReturn {
-    if workspaces.workspaces.len() == 0 {
-        return "No workspaces".into();
+impl Row<'_> {
+    fn is_local(&self) -> bool {
+        matches!(self.workspace.source, WorkspaceSource::Local(_))
+    }
+
+    fn source(&self) -> &'static str {
+        match self.workspace.source {
+            WorkspaceSource::Registry => "registry",
+            WorkspaceSource::Include(_) => "include",
+            WorkspaceSource::Local(_) => "local",
+        }
     }
+}
 
-    for workspace in workspaces.workspaces {
+fn print_text(workspaces: &Workspaces, rows: &[Row]) {
+    for row in rows {
+        let workspace = row.workspace;
         println!(
-            "Workspace: {}, shell: {}, with working directory: {}, runs: {}",
+            "{}: {}, shell: {}, with working directory: {}{}, runs: {}{}, from: {}{}",
+            if row.is_local() {
+                "Local workspace"
+            } else {
+                "Workspace"
+            },
             workspace.name,
             workspace.shell,
             workspace.path.display(),
-            workspace.init_commands.join(", ")
+            resolved_note(row),
+            join_lossy(&workspace.init_commands),
+            env_and_tags_note(workspace),
+            workspaces.source_location(workspace),
+            if row.is_local() {
+                " (not registered)".to_string()
+            } else {
+                usage_note(workspace)
+            }
         )
     }
-    .into()
 }
 
-pub fn delete(mut workspaces: Workspaces, name: Option<String>, confirm: bool) -> CommandReturn {
-    if workspaces.workspaces.len() == 0 {
-        return "No workspaces".into();
+fn print_table(workspaces: &Workspaces, rows: &[Row]) {
+    let header = [
+        "NAME",
+        "PATH",
+        "SHELL",
+        "TAGS",
+        "LAUNCHES",
+        "LAST USED",
+        "FROM",
+    ]
+    .map(String::from)
+    .to_vec();
+    let mut lines = vec![header];
+    for row in rows {
+        let workspace = row.workspace;
+        lines.push(vec![
+            workspace.name.clone(),
+            format!("{}{}", workspace.path.display(), resolved_note(row)),
+            workspace.shell.clone(),
+            workspace.tags.join(","),
+            workspace.launch_count.to_string(),
+            workspace
+                .last_used_at
+                .map_or("never".to_string(), timestamps::format),
+            workspaces.source_location(workspace),
+        ]);
+    }
+
+    let mut widths = vec![0; lines[0].len()];
+    for line in &lines {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for line in &lines {
+        let cells: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+fn print_tsv(workspaces: &Workspaces, rows: &[Row]) {
+    println!("name\tpath\tresolved_path\tshell\tinit_commands\ttags\tsource\tfrom\tcreated_at\tlast_used_at\tlaunch_count");
+    for row in rows {
+        let workspace = row.workspace;
+        let commands: Vec<String> = workspace
+            .init_commands
+            .iter()
+            .map(|command| command.to_string_lossy().to_string())
+            .collect();
+        let fields = [
+            workspace.name.clone(),
+            workspace.path.display().to_string(),
+            row.resolved
+                .as_ref()
+                .map_or(String::new(), |path| path.display().to_string()),
+            workspace.shell.clone(),
+            commands.join(";"),
+            workspace.tags.join(","),
+            row.source().to_string(),
+            workspaces.source_location(workspace),
+            workspace
+                .created_at
+                .map_or(String::new(), |time| time.to_string()),
+            workspace
+                .last_used_at
+                .map_or(String::new(), |time| time.to_string()),
+            workspace.launch_count.to_string(),
+        ];
+        // Tabs and line breaks in a value would break the columns
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|field| field.replace(['\t', '\n', '\r'], " "))
+            .collect();
+        println!("{}", fields.join("\t"));
+    }
+}
+
+/// A workspace as printed by `ws list --format json`
+#[derive(Serialize)]
+struct JsonWorkspace<'a> {
+    #[serde(skip_serializing_if = "str::is_empty")]
+    id: &'a str,
+    name: &'a str,
+    path: String,
+    resolved_path: Option<String>,
+    shell: &'a str,
+    init_commands: Vec<String>,
+    env: &'a BTreeMap<String, String>,
+    tags: &'a [String],
+    source: &'static str,
+    from: String,
+    created_at: Option<u64>,
+    last_used_at: Option<u64>,
+    launch_count: u64,
+}
+
+fn json_workspace<'a>(workspaces: &Workspaces, row: &Row<'a>) -> JsonWorkspace<'a> {
+    let workspace = row.workspace;
+    JsonWorkspace {
+        id: &workspace.id,
+        name: &workspace.name,
+        path: workspace.path.to_string_lossy().to_string(),
+        resolved_path: row
+            .resolved
+            .as_ref()
+            .ok()
+            .map(|path| path.to_string_lossy().to_string()),
+        shell: &workspace.shell,
+        init_commands: workspace
+            .init_commands
+            .iter()
+            .map(|command| command.to_string_lossy().to_string())
+            .collect(),
+        env: &workspace.env,
+        tags: &workspace.tags,
+        source: row.source(),
+        from: workspaces.source_location(workspace),
+        created_at: workspace.created_at,
+        last_used_at: workspace.last_used_at,
+        launch_count: workspace.launch_count,
+    }
+}
+
+/// A workspace as shown by `ws show`, with what was checked on this machine
+#[derive(Serialize)]
+struct JsonDetails<'a> {
+    #[serde(flatten)]
+    workspace: JsonWorkspace<'a>,
+    path_exists: bool,
+    shell_executable: Option<String>,
+}
+
+pub fn show(workspaces: Workspaces, name: String) -> CommandReturn {
+    let workspace = match workspaces.find(&name) {
+        Some(workspace) => workspace,
+        None => return not_found(),
+    };
+    let row = Row {
+        workspace,
+        resolved: workspaces.resolve(workspace),
+    };
+    let path_exists = row.resolved.as_ref().is_ok_and(|path| path.is_dir());
+    let shell_executable = Shell::find_executable(&workspace.shell);
+
+    if output::is_json() {
+        output::data(&JsonDetails {
+            workspace: json_workspace(&workspaces, &row),
+            path_exists,
+            shell_executable: shell_executable.map(|path| path.to_string_lossy().to_string()),
+        });
+        return ().into();
+    }
+
+    println!(
+        "{}{}",
+        workspace.name,
+        if workspace.id.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", workspace.id)
+        }
+    );
+    detail("Path", workspace.path.display());
+    match &row.resolved {
+        Ok(path) => detail(
+            "Resolved",
+            format!(
+                "{} ({})",
+                path.display(),
+                if path_exists { "exists" } else { "missing" }
+            ),
+        ),
+        Err(err) => detail("Resolved", err),
+    }
+    match &shell_executable {
+        Some(path) => detail("Shell", format!("{} ({})", workspace.shell, path.display())),
+        None => detail("Shell", format!("{} (not found on PATH)", workspace.shell)),
+    }
+    if workspace.init_commands.is_empty() {
+        detail("Init commands", "none");
+    } else {
+        println!("  Init commands:");
+        for (index, command) in workspace.init_commands.iter().enumerate() {
+            println!("    {}. {}", index + 1, command.to_string_lossy());
+        }
+    }
+    if workspace.env.is_empty() {
+        detail("Environment", "none");
+    } else {
+        println!("  Environment:");
+        for (name, value) in &workspace.env {
+            println!("    {}={}", name, value);
+        }
+    }
+    detail(
+        "Tags",
+        if workspace.tags.is_empty() {
+            "none".to_string()
+        } else {
+            workspace.tags.join(", ")
+        },
+    );
+    if workspace.source == WorkspaceSource::Registry {
+        detail(
+            "Created",
+            workspace
+                .created_at
+                .map_or("unknown".to_string(), timestamps::format),
+        );
+        detail(
+            "Last used",
+            workspace
+                .last_used_at
+                .map_or("never".to_string(), timestamps::format),
+        );
+        detail("Launches", workspace.launch_count);
+    }
+    detail(
+        "From",
+        format!(
+            "{} ({})",
+            workspaces.source_location(workspace),
+            row.source()
+        ),
+    );
+    ().into()
+}
+
+/// Prints one field of `ws show` with the values lined up
+fn detail(label: &str, value: impl Display) {
+    println!("  {:<15}{}", format!("{}:", label), value);
+}
+
+fn print_json(workspaces: &Workspaces, rows: &[Row]) -> CommandReturn {
+    let entries: Vec<JsonWorkspace> = rows
+        .iter()
+        .map(|row| json_workspace(workspaces, row))
+        .collect();
+
+    match serde_json::to_string_pretty(&entries) {
+        Ok(json) => {
+            println!("{}", json);
+            ().into()
+        }
+        Err(err) => err.to_string().into(),
+    }
+}
+
+/// Usage is only tracked for registered workspaces
+fn usage_note(workspace: &Workspace) -> String {
+    if workspace.source != WorkspaceSource::Registry {
+        return String::new();
+    }
+
+    format!(
+        ", created: {}, last used: {}, launches: {}",
+        workspace
+            .created_at
+            .map_or("unknown".to_string(), timestamps::format),
+        workspace
+            .last_used_at
+            .map_or("never".to_string(), timestamps::format),
+        workspace.launch_count
+    )
+}
+
+fn env_and_tags_note(workspace: &Workspace) -> String {
+    let mut note = String::new();
+    if !workspace.env.is_empty() {
+        let env: Vec<String> = workspace
+            .env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        note.push_str(&format!(", env: {}", env.join(" ")));
+    }
+    if !workspace.tags.is_empty() {
+        note.push_str(&format!(", tags: {}", workspace.tags.join(", ")));
+    }
+    note
+}
+
+fn join_lossy(values: &[OsString]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Shows where a workspace path points on this machine when that isn't the stored path
+fn resolved_note(row: &Row) -> String {
+    match &row.resolved {
+        Ok(path) if *path == row.workspace.path => String::new(),
+        Ok(path) => format!(" (resolves to {})", path.display()),
+        Err(err) => format!(" ({})", err),
+    }
+}
+
+pub fn delete(
+    mut workspaces: Workspaces,
+    name: Option<String>,
+    tags: Vec<String>,
+    confirm: bool,
+) -> CommandReturn {
+    if !tags.is_empty() {
+        return delete_tagged(workspaces, tags, confirm);
     }
 
     if let Some(workspace_name) = name {
@@ -87,57 +719,902 @@ pub fn delete(mut workspaces: Workspaces, name: Option<String>, confirm: bool) -
             .iter()
             .find(|w| w.name == workspace_name)
         {
-            if confirm {
-                return workspaces.remove_from_file(workspace).into();
+            if let Err(err) = workspaces.remove_from_file(workspace) {
+                return err.into();
             }
-
-            let input: String;
-            print!(
-                "Are you sure you want to delete workspace {}? (y/n) ",
-                workspace_name
+            output::data(&json!({ "deleted": [workspace_name] }));
+            say!(
+                "Moved workspace {} to the trash, {}",
+                workspace_name,
+                restore_hint(&[workspace_name.as_str()])
             );
-            if let Err(err) = io::stdout().flush() {
-                return err.to_string().as_str().into();
+            return ().into();
+        } else {
+            return not_found();
+        }
+    }
+
+    let names: Vec<String> = workspaces
+        .workspaces
+        .iter()
+        .filter(|w| w.source == WorkspaceSource::Registry)
+        .map(|w| w.name.clone())
+        .collect();
+    // Included workspaces are read-only, so with only those there is nothing to delete
+    if names.is_empty() {
+        return WorkspaceError::coded(
+            ErrorCode::Empty,
+            "No registered workspaces to delete".to_string(),
+        )
+        .into();
+    }
+    if let Err(err) = workspaces.clear() {
+        return err.into();
+    }
+    output::data(&json!({ "deleted": names }));
+    say!("Moved all workspaces to the trash, {}", restore_hint(&[]));
+    ().into()
+}
+
+/// How to get deleted workspaces back, the same however they were deleted
+fn restore_hint(names: &[&str]) -> String {
+    match names {
+        [name] => format!("restore it with `ws trash restore {}`", name),
+        _ => "restore them with `ws trash restore <name>`".to_string(),
+    }
+}
+
+/// Moves every registered workspace with all of `tags` to the trash after a single confirmation
+fn delete_tagged(mut workspaces: Workspaces, tags: Vec<String>, confirm: bool) -> CommandReturn {
+    let tagged: Vec<&Workspace> = workspaces
+        .workspaces
+        .iter()
+        .filter(|w| w.source == WorkspaceSource::Registry && w.has_tags(&tags))
+        .collect();
+    if tagged.is_empty() {
+        return WorkspaceError::coded(
+            ErrorCode::NotFound,
+            format!("No registered workspace is tagged {}", tags.join(", ")),
+        )
+        .into();
+    }
+
+    if !confirm {
+        for workspace in &tagged {
+            say!("{} ({})", workspace.name, workspace.path.display());
+        }
+        match ask(&format!(
+            "Move these {} workspaces to the trash?",
+            tagged.len()
+        )) {
+            Ok(true) => {}
+            Ok(false) => return cancelled("Delete cancelled"),
+            Err(err) => return err.into(),
+        }
+    }
+
+    match workspaces.remove_tagged(&tags) {
+        Ok(removed) => {
+            let names: Vec<&str> = removed.iter().map(|w| w.name.as_str()).collect();
+            output::data(&json!({ "deleted": names }));
+            say!(
+                "Moved {} workspaces to the trash, {}",
+                removed.len(),
+                restore_hint(&names)
+            )
+        }
+        Err(err) => return err.into(),
+    }
+    ().into()
+}
+
+pub fn modify(mut workspaces: Workspaces, name: String, changes: ModifyArgs) -> CommandReturn {
+    let existing = match workspaces.find(&name) {
+        Some(workspace) => workspace.clone(),
+        None => return not_found(),
+    };
+    let patch = match new_patch(changes) {
+        Ok(patch) => patch,
+        Err(err) => return err,
+    };
+    if patch.is_empty() {
+        return invalid_argument("Nothing to change, see `ws modify --help`".to_string());
+    }
+
+    let result = if existing.source == WorkspaceSource::Registry {
+        workspaces.patch(&existing.id, &patch)
+    } else {
+        // Modifying an included or local workspace registers an override instead
+        let mut workspace = Workspace {
+            id: String::new(),
+            source: WorkspaceSource::Registry,
+            ..existing
+        };
+        match patch.apply(&mut workspace) {
+            Ok(()) => workspaces.add(workspace),
+            Err(err) => Err(err),
+        }
+    };
+    if let Err(err) = result {
+        return err.into();
+    }
+    workspace_data(&workspaces, &name);
+    ().into()
+}
+
+pub fn edit(mut workspaces: Workspaces, name: String) -> CommandReturn {
+    let existing = match workspaces.find(&name) {
+        Some(workspace) => workspace.clone(),
+        None => return not_found(),
+    };
+    let current_dir = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(err) => return err.to_string().into(),
+    };
+    let contents = match crate::edit::to_toml(&existing) {
+        Ok(contents) => contents,
+        Err(err) => return err.into(),
+    };
+
+    let file = match create_temp_file(&contents) {
+        Ok(file) => file,
+        Err(err) => return err.into(),
+    };
+    let edited = edit_until_valid(&file, &existing, &current_dir);
+    let _ = fs::remove_file(&file);
+
+    let workspace = match edited {
+        Ok(Some((edited, workspace))) if edited != contents => workspace,
+        Ok(Some(_)) => {
+            say!("No changes to workspace {}", name);
+            return ().into();
+        }
+        Ok(None) => return cancelled("Edit cancelled"),
+        Err(err) => return err.into(),
+    };
+
+    let new_name = workspace.name.clone();
+    let result = if existing.source == WorkspaceSource::Registry {
+        workspaces.replace(&existing.id, workspace)
+    } else {
+        // Editing an included or local workspace registers an override instead
+        workspaces.add(Workspace {
+            id: String::new(),
+            source: WorkspaceSource::Registry,
+            ..workspace
+        })
+    };
+    if let Err(err) = result {
+        return err.into();
+    }
+    workspace_data(&workspaces, &new_name);
+    say!("Updated workspace {}", new_name);
+    ().into()
+}
+
+/// Writes `contents` to a new file with an unpredictable name in the temporary directory. The
+/// file is created exclusively, so an existing file or symlink there is never written through.
+fn create_temp_file(contents: &str) -> Result<PathBuf, WorkspaceError> {
+    loop {
+        let file = env::temp_dir().join(format!("ws-edit-{}.toml", registry::generate_id(&[])));
+        let write_error = |err: io::Error| {
+            WorkspaceError::coded(
+                ErrorCode::Io,
+                format!("Could not write {}: {}", file.display(), err),
+            )
+        };
+        let mut created = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&file)
+        {
+            Ok(created) => created,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(write_error(err)),
+        };
+        created
+            .write_all(contents.as_bytes())
+            .map_err(write_error)?;
+        return Ok(file);
+    }
+}
+
+/// Opens the editor on `file` until it holds a valid definition, returning the contents and
+/// the workspace they define, or `None` if the user gave up
+fn edit_until_valid(
+    file: &Path,
+    original: &Workspace,
+    base: &Path,
+) -> Result<Option<(String, Workspace)>, WorkspaceError> {
+    loop {
+        run_editor(file)?;
+        let contents = fs::read_to_string(file).map_err(|err| {
+            WorkspaceError::coded(
+                ErrorCode::Io,
+                format!("Could not read {}: {}", file.display(), err),
+            )
+        })?;
+
+        match crate::edit::parse(&contents, original, base) {
+            Ok(Some(workspace)) => return Ok(Some((contents, workspace))),
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                let message = format!("The definition is invalid: {}", err);
+                if output::is_json() || !io::stdin().is_terminal() {
+                    return Err(WorkspaceError::coded(ErrorCode::InvalidArgument, message));
+                }
+                say!("{}", message);
+                if !ask("Edit it again?")? {
+                    return Ok(None);
+                }
             }
-            scan!("{}", input);
-            if input != "y" {
-                return WorkspaceError::new("Clear cancelled".into(), Severity::Message).into();
+        }
+    }
+}
+
+/// Runs `$VISUAL` or `$EDITOR`, which may include arguments, on `file`
+fn run_editor(file: &Path) -> Result<(), WorkspaceError> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(target_os = "windows") {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
             }
+        });
+
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(file)
+        .status()
+        .map_err(|err| {
+            WorkspaceError::coded(
+                ErrorCode::Io,
+                format!("Could not run the editor {}: {}", editor, err),
+            )
+        })?;
+    if !status.success() {
+        return Err(format!("The editor {} exited with {}", editor, status).into());
+    }
+    Ok(())
+}
 
-            return workspaces.remove_from_file(workspace).into();
+pub fn rename(mut workspaces: Workspaces, name: String, new_name: String) -> CommandReturn {
+    let existing = match workspaces.find(&name) {
+        Some(workspace) => workspace.clone(),
+        None => return not_found(),
+    };
+    if new_name.trim().is_empty() {
+        return invalid_argument("The new name can't be empty".to_string());
+    }
+
+    if let Err(err) = workspaces.rename(&existing, &new_name) {
+        return err.into();
+    }
+    workspace_data(&workspaces, &new_name);
+    if existing.source == WorkspaceSource::Registry {
+        say!("Renamed workspace {} to {}", name, new_name);
+    } else {
+        say!(
+            "Registered workspace {} as a copy of {} from {}",
+            new_name,
+            name,
+            workspaces.source_location(&existing)
+        );
+    }
+    ().into()
+}
+
+pub fn duplicate(
+    mut workspaces: Workspaces,
+    name: String,
+    new_name: String,
+    changes: ModifyArgs,
+) -> CommandReturn {
+    let existing = match workspaces.find(&name) {
+        Some(workspace) => workspace.clone(),
+        None => return not_found(),
+    };
+    if new_name.trim().is_empty() {
+        return invalid_argument("The new name can't be empty".to_string());
+    }
+    let patch = match new_patch(changes) {
+        Ok(patch) => patch,
+        Err(err) => return err,
+    };
+
+    // The copy starts its own history and usage
+    let mut copy = Workspace {
+        id: String::new(),
+        name: new_name.clone(),
+        created_at: None,
+        last_used_at: None,
+        launch_count: 0,
+        source: WorkspaceSource::Registry,
+        ..existing
+    };
+    if let Err(err) = patch.apply(&mut copy) {
+        return err.into();
+    }
+    if let Err(err) = workspaces.add(copy) {
+        return err.into();
+    }
+    workspace_data(&workspaces, &new_name);
+    say!("Copied workspace {} to {}", name, new_name);
+    ().into()
+}
+
+fn new_patch(changes: ModifyArgs) -> Result<Patch, CommandReturn> {
+    if let Some(tag) = changes.tags.iter().find(|tag| !is_valid_tag(tag)) {
+        return Err(invalid_tag(tag));
+    }
+    let path = match changes.path {
+        Some(path) => match env::current_dir() {
+            Ok(current_dir) => Some(paths::normalize(&path, &current_dir)),
+            Err(err) => return Err(err.to_string().into()),
+        },
+        None => None,
+    };
+    let init_commands = match (changes.text, changes.command_path) {
+        (Some(text), _) => Some(text.split(';').map(OsString::from).collect()),
+        (None, Some(command_path)) => Some(vec![command_path.into_os_string()]),
+        (None, None) => None,
+    };
+
+    let mut insert_commands = Vec::new();
+    for pair in changes.insert_commands.chunks(2) {
+        let position = match pair[0].parse() {
+            Ok(position) => position,
+            Err(_) => {
+                return Err(invalid_argument(format!(
+                    "Invalid position {:?} for --insert-cmd",
+                    pair[0]
+                )))
+            }
+        };
+        insert_commands.push((position, OsString::from(&pair[1])));
+    }
+
+    let mut set_env = Vec::new();
+    for variable in &changes.set_env {
+        match variable.split_once('=') {
+            Some((name, value)) if !name.is_empty() => {
+                set_env.push((name.to_string(), value.to_string()))
+            }
+            _ => {
+                return Err(invalid_argument(format!(
+                    "Invalid variable {:?} for --set-env, expected NAME=VALUE",
+                    variable
+                )))
+            }
+        }
+    }
+
+    Ok(Patch {
+        path,
+        shell: changes.shell,
+        init_commands,
+        remove_commands: changes.remove_commands,
+        insert_commands,
+        move_commands: changes
+            .move_commands
+            .chunks(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect(),
+        add_commands: changes
+            .add_commands
+            .into_iter()
+            .map(OsString::from)
+            .collect(),
+        set_env,
+        unset_env: changes.unset_env,
+        tags: if changes.tags.is_empty() {
+            None
         } else {
-            return "Workspace not found".into();
+            Some(changes.tags)
+        },
+    })
+}
+
+fn invalid_argument(message: String) -> CommandReturn {
+    WorkspaceError::coded(ErrorCode::InvalidArgument, message).into()
+}
+
+pub fn trash_list(workspaces: Workspaces) -> CommandReturn {
+    if output::is_json() {
+        let trashed: Vec<_> = workspaces
+            .trash
+            .iter()
+            .map(|t| {
+                json!({
+                    "id": t.workspace.id,
+                    "name": t.workspace.name,
+                    "path": t.workspace.path,
+                    "deleted_at": t.deleted_at,
+                })
+            })
+            .collect();
+        output::data(&trashed);
+        return ().into();
+    }
+    if workspaces.trash.is_empty() {
+        return WorkspaceError::new("The trash is empty".into(), Severity::Message)
+            .with_code(ErrorCode::Empty)
+            .into();
+    }
+
+    for trashed in &workspaces.trash {
+        println!(
+            "Workspace: {}, with working directory: {}, deleted at: {}",
+            trashed.workspace.name,
+            trashed.workspace.path.display(),
+            timestamps::format(trashed.deleted_at)
+        );
+    }
+    ().into()
+}
+
+pub fn trash_restore(mut workspaces: Workspaces, name: String) -> CommandReturn {
+    if let Err(err) = workspaces.restore(&name) {
+        return err.into();
+    }
+    output::data(&json!({ "restored": name }));
+    say!("Restored workspace {}", name);
+    ().into()
+}
+
+pub fn trash_purge(
+    mut workspaces: Workspaces,
+    older_than: Option<String>,
+    all: bool,
+    confirm: bool,
+    retention: &str,
+) -> CommandReturn {
+    let age = if all {
+        None
+    } else {
+        let age = older_than.as_deref().unwrap_or(retention);
+        match humantime::parse_duration(age) {
+            Ok(age) => Some(age.as_secs()),
+            Err(err) => {
+                return WorkspaceError::coded(
+                    ErrorCode::InvalidArgument,
+                    format!("Invalid age {:?}: {}", age, err),
+                )
+                .into()
+            }
+        }
+    };
+
+    if !confirm {
+        let question = match age {
+            Some(age) => format!(
+                "Are you sure you want to permanently delete workspaces trashed more than {} ago?",
+                humantime::format_duration(std::time::Duration::from_secs(age))
+            ),
+            None => "Are you sure you want to permanently delete all trashed workspaces?".into(),
+        };
+        match ask(&question) {
+            Ok(true) => {}
+            Ok(false) => return cancelled("Purge cancelled"),
+            Err(err) => return err.into(),
         }
     }
 
-    if confirm {
-        return workspaces.clear().into();
+    match workspaces.purge(age) {
+        Ok(purged) => {
+            let names: Vec<&str> = purged.iter().map(|t| t.workspace.name.as_str()).collect();
+            output::data(&json!({ "purged": names }));
+            say!("Purged {} workspaces", purged.len())
+        }
+        Err(err) => return err.into(),
     }
+    ().into()
+}
+
+fn cancelled(message: &str) -> CommandReturn {
+    WorkspaceError::new(message.to_string(), Severity::Message)
+        .with_code(ErrorCode::Cancelled)
+        .into()
+}
 
+/// Asks a yes/no question on the terminal, which `--json` can't, so `--yes` is required there
+fn ask(question: &str) -> Result<bool, WorkspaceError> {
+    if output::is_json() {
+        return Err(output::confirmation_required("--yes"));
+    }
     let input: String;
-    print!("Are you sure you want to clear all workspaces? (y/n) ");
+    print!("{} (y/n) ", question);
     if let Err(err) = io::stdout().flush() {
-        return err.to_string().as_str().into();
+        return Err(err.to_string().into());
     }
     scan!("{}", input);
-    if input != "y" {
-        return WorkspaceError::new("Clear cancelled".into(), Severity::Message).into();
+    Ok(input == "y")
+}
+
+pub fn undo(mut workspaces: Workspaces, count: usize) -> CommandReturn {
+    match workspaces.undo(count) {
+        Ok(reverted) => {
+            let undone: Vec<_> = reverted
+                .iter()
+                .rev()
+                .map(|e| json!({ "sequence": e.sequence, "description": e.description }))
+                .collect();
+            output::data(&json!({ "reverted": undone }));
+            for entry in reverted.iter().rev() {
+                say!("Reverted #{}: {}", entry.sequence, entry.description);
+            }
+            ().into()
+        }
+        Err(err) => err.into(),
     }
+}
+
+pub fn history(workspaces: Workspaces, limit: usize) -> CommandReturn {
+    let journal = match &workspaces.journal {
+        Some(journal) => journal,
+        None => return "No journal is kept for this registry".into(),
+    };
 
-    workspaces.clear().into()
+    let entries = match journal.entries() {
+        Ok(entries) => entries,
+        Err(err) => return err.into(),
+    };
+    let reverted: Vec<u64> = entries
+        .iter()
+        .flat_map(|entry| entry.reverts.iter().copied())
+        .collect();
+    if output::is_json() {
+        let shown: Vec<_> = entries
+            .iter()
+            .rev()
+            .take(limit)
+            .map(|entry| {
+                json!({
+                    "sequence": entry.sequence,
+                    "timestamp": entry.timestamp,
+                    "description": entry.description,
+                    "undone": reverted.contains(&entry.sequence),
+                })
+            })
+            .collect();
+        output::data(&shown);
+        return ().into();
+    }
+    if entries.is_empty() {
+        return WorkspaceError::new("No recorded changes".into(), Severity::Message)
+            .with_code(ErrorCode::Empty)
+            .into();
+    }
+
+    for entry in entries.iter().rev().take(limit) {
+        println!(
+            "#{} {} {}{}",
+            entry.sequence,
+            humantime::format_rfc3339_seconds(entry.time()),
+            entry.description,
+            if reverted.contains(&entry.sequence) {
+                " (undone)"
+            } else {
+                ""
+            }
+        );
+    }
+    ().into()
 }
 
-pub fn modify(
-    mut workspaces: Workspaces,
+pub fn fsck(file: &Path, fix: bool) -> CommandReturn {
+    // Hold the lock so no other invocation writes the registry while it is being repaired
+    let _lock = match RegistryLock::acquire(file) {
+        Ok(lock) => lock,
+        Err(err) => return err.into(),
+    };
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(err) => {
+            return WorkspaceError::coded(
+                ErrorCode::Io,
+                format!("Could not read {}: {}", file.display(), err),
+            )
+            .into()
+        }
+    };
+
+    let check = crate::fsck::check(&contents);
+    fsck_data(file, &check, &[], None);
+    if check.issues.is_empty() {
+        say!("{}: no problems found", file.display());
+        return ().into();
+    }
+
+    for issue in &check.issues {
+        match &issue.repair {
+            Some(repair) => say!(
+                "{}:{}: {} (fix: {})",
+                file.display(),
+                issue.line,
+                issue.message,
+                repair.describe()
+            ),
+            None => say!(
+                "{}:{}: {} (must be fixed by hand)",
+                file.display(),
+                issue.line,
+                issue.message
+            ),
+        }
+    }
+
+    if check.is_unreadable() {
+        return WorkspaceError::coded(
+            ErrorCode::InvalidRegistry,
+            format!(
+                "{} can't be read, fix the problems above by hand",
+                file.display()
+            ),
+        )
+        .into();
+    }
+
+    let mut accepted = Vec::new();
+    for (index, issue) in check.issues.iter().enumerate() {
+        let repair = match &issue.repair {
+            Some(repair) => repair,
+            None => continue,
+        };
+        if fix {
+            accepted.push(index);
+        } else if io::stdin().is_terminal() && !output::is_json() {
+            match ask(&format!("Line {}: {}?", issue.line, repair.describe())) {
+                Ok(true) => accepted.push(index),
+                Ok(false) => {}
+                Err(err) => return err.into(),
+            }
+        }
+    }
+
+    if !accepted.is_empty() {
+        let backup = registry::backup_path(file);
+        if let Err(err) = fs::copy(file, &backup) {
+            return format!("Could not back up {}: {}", file.display(), err).into();
+        }
+        if let Err(err) = check.repair(&accepted).save(file) {
+            return err.into();
+        }
+        fsck_data(file, &check, &accepted, Some(&backup));
+        say!(
+            "Repaired {} problems in {} (backup at {})",
+            accepted.len(),
+            file.display(),
+            backup.display()
+        );
+    }
+
+    let remaining = check.issues.len() - accepted.len();
+    if remaining > 0 {
+        return WorkspaceError::new(
+            format!(
+                "{} problems left, run `ws fsck --fix` to repair them",
+                remaining
+            ),
+            Severity::Warning,
+        )
+        .into();
+    }
+    ().into()
+}
+
+fn fsck_data(file: &Path, check: &crate::fsck::Check, accepted: &[usize], backup: Option<&Path>) {
+    let issues: Vec<_> = check
+        .issues
+        .iter()
+        .enumerate()
+        .map(|(index, issue)| {
+            json!({
+                "line": issue.line,
+                "message": issue.message,
+                "repair": issue.repair.as_ref().map(|repair| repair.describe()),
+                "repaired": accepted.contains(&index),
+            })
+        })
+        .collect();
+    output::data(&json!({ "file": file, "issues": issues, "backup": backup }));
+}
+
+pub fn profile_list(file_config: &FileConfig) -> CommandReturn {
+    let current = file_config.profile_name();
+    let names =
+        std::iter::once(DEFAULT_PROFILE).chain(file_config.profiles.keys().map(|n| n.as_str()));
+
+    let mut listed = Vec::new();
+    for name in names {
+        let profile = match file_config.find_profile(name) {
+            Some(profile) => profile,
+            None => continue,
+        };
+        listed.push(json!({
+            "name": name,
+            "file": profile.database_file.as_ref().unwrap_or(&profile.workspaces_file),
+            "current": name == current,
+        }));
+        say!(
+            "{} {}: {}",
+            if name == current { "*" } else { " " },
+            name,
+            profile
+                .database_file
+                .unwrap_or(profile.workspaces_file)
+                .display()
+        );
+    }
+    output::data(&listed);
+    ().into()
+}
+
+pub fn profile_create(
+    file_config: &FileConfig,
     name: String,
-    path: PathBuf,
-    shell: Option<String>,
-    text: Option<String>,
-    cmd_path: Option<PathBuf>,
+    file: Option<PathBuf>,
 ) -> CommandReturn {
-    delete(workspaces.clone(), Some(name.clone()), false);
-    workspaces.workspaces.retain(|w| w.name != name);
-    add(workspaces, name, path, shell, text, cmd_path).into()
+    if file_config.find_profile(&name).is_some() {
+        return WorkspaceError::coded(
+            ErrorCode::AlreadyExists,
+            format!("Profile {} already exists", name),
+        )
+        .into();
+    }
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        return WorkspaceError::coded(
+            ErrorCode::InvalidArgument,
+            format!("Invalid profile name {:?}", name),
+        )
+        .into();
+    }
+
+    let file = match file {
+        Some(file) => file,
+        None => file_config
+            .save_dir
+            .join(format!("workspaces-{}.toml", name)),
+    };
+    let profile = Profile {
+        workspaces_file: file.clone(),
+        database_file: None,
+    };
+    if let Err(err) = FileConfig::config_file_path()
+        .and_then(|config_file| FileConfig::add_profile(&config_file, &name, &profile))
+    {
+        return WorkspaceError::coded(ErrorCode::Config, err).into();
+    }
+
+    output::data(&json!({ "profile": name, "file": file }));
+    say!(
+        "Created profile {} with its registry at {}, use it with `ws --profile {}` or `ws profile switch {}`",
+        name,
+        file.display(),
+        name,
+        name
+    );
+    ().into()
+}
+
+pub fn profile_switch(file_config: &FileConfig, name: String) -> CommandReturn {
+    if file_config.find_profile(&name).is_none() {
+        return WorkspaceError::coded(
+            ErrorCode::NotFound,
+            format!(
+                "Unknown profile {}, create it with `ws profile create {}`",
+                name, name
+            ),
+        )
+        .into();
+    }
+
+    let active = Some(name.as_str()).filter(|name| *name != DEFAULT_PROFILE);
+    if let Err(err) = FileConfig::config_file_path()
+        .and_then(|config_file| FileConfig::set_profile(&config_file, active))
+    {
+        return WorkspaceError::coded(ErrorCode::Config, err).into();
+    }
+
+    output::data(&json!({ "profile": name }));
+    say!("Switched to profile {}", name);
+    match &file_config.selected_profile {
+        Some(selected) if *selected != name => WorkspaceError::new(
+            format!(
+                "profile {} is still selected for this shell by --profile or {}",
+                selected,
+                EnvVar::Profile
+            ),
+            Severity::Warning,
+        )
+        .into(),
+        _ => ().into(),
+    }
+}
+
+pub fn register(mut workspaces: Workspaces) -> CommandReturn {
+    let current_dir = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(err) => return err.to_string().into(),
+    };
+
+    let workspace = match local::discover(&current_dir) {
+        Ok(Some(workspace)) => workspace,
+        Ok(None) => {
+            return WorkspaceError::coded(
+                ErrorCode::NotFound,
+                format!(
+                    "No {} found in {} or its parents",
+                    local::LOCAL_FILE_NAME,
+                    current_dir.display()
+                ),
+            )
+            .into()
+        }
+        Err(err) => return err.into(),
+    };
+
+    if workspaces
+        .workspaces
+        .iter()
+        .any(|w| w.name == workspace.name)
+    {
+        return WorkspaceError::coded(
+            ErrorCode::AlreadyExists,
+            format!("Workspace {} is already registered", workspace.name),
+        )
+        .into();
+    }
+
+    let name = workspace.name.clone();
+    if let Err(err) = workspaces.add(workspace) {
+        return err.into();
+    }
+    workspace_data(&workspaces, &name);
+    say!("Registered workspace {}", name);
+    ().into()
+}
+
+pub fn import(mut workspaces: Workspaces, file: PathBuf) -> CommandReturn {
+    let registry = match fs::read_to_string(&file) {
+        Ok(contents) => match Registry::parse(&contents) {
+            Ok(registry) => registry,
+            Err(err) => {
+                return WorkspaceError::coded(
+                    ErrorCode::InvalidRegistry,
+                    format!("Could not parse {}: {}", file.display(), err),
+                )
+                .into()
+            }
+        },
+        Err(err) => {
+            return WorkspaceError::coded(
+                ErrorCode::Io,
+                format!("Could not read {}: {}", file.display(), err),
+            )
+            .into()
+        }
+    };
+
+    match workspaces.import(&registry) {
+        Ok(imported) => {
+            output::data(&json!({ "imported": imported, "total": registry.workspaces.len() }));
+            say!(
+                "Imported {} of {} workspaces into {}",
+                imported,
+                registry.workspaces.len(),
+                workspaces.store.location()
+            )
+        }
+        Err(err) => return err.into(),
+    }
+    ().into()
 }
 
 // pub fn set_config(config: Config) -> CommandReturn {
@@ -173,13 +1650,9 @@ pub fn modify(
 // }
 
 pub fn help(config: Config) -> CommandReturn {
-    if let Some(args) = config.args {
-        if args.len() > 1 {
-            println!("Help for {}", args[1]).into()
-        } else {
-            println!("Help for all commands").into()
-        }
-    } else {
-        println!("Help for all commands").into()
+    match config.args {
+        Some(args) if args.len() > 1 => println!("Help for {}", args[1]),
+        _ => println!("Help for all commands"),
     }
+    ().into()
 }
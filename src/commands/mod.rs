@@ -1,5 +1,5 @@
-use crate::{CommandReturn, Workspaces};
-use clap::Subcommand;
+use crate::{filter::Filter, CommandReturn, FileConfig, Workspaces};
+use clap::{Args, Subcommand, ValueEnum};
 pub use command_dict::*;
 use std::fmt::{Display, Formatter};
 
@@ -9,103 +9,462 @@ pub mod command_dict;
 pub enum Command {
     /// Initialize a workspace
     Init {
-        /// Name of the workspace to initialize
-        name: String,
+        /// Name of the workspace to initialize (picked interactively if omitted)
+        name: Option<String>,
+
+        /// Only pick among workspaces with this tag (repeat to require several)
+        #[clap(long = "tag", conflicts_with = "name")]
+        tags: Vec<String>,
     },
     /// Adds a workspace to the list
     Add {
-        /// Name of the workspace to add
-        name: String,
+        /// Name of the workspace to add, asked for on a terminal if left out
+        #[clap(requires = "path")]
+        name: Option<String>,
 
         /// The path to the workspace (this will be the directory that will be cd'd into)
-        path: std::path::PathBuf,
+        path: Option<std::path::PathBuf>,
 
         /// The shell to execute
         #[clap(short = 's', long = "shell")]
         shell: Option<String>,
 
         /// The init commands in text form
-        #[clap(short = 't', long = "text")]
+        #[clap(short = 't', long = "text", requires = "name")]
         text: Option<String>,
 
         /// The init commands in file form
-        #[clap(short = 'c', long = "cmd-path")]
+        #[clap(short = 'c', long = "cmd-path", requires = "name")]
         command_path: Option<std::path::PathBuf>,
+
+        /// A tag to group the workspace by, e.g. backend (repeat for several)
+        #[clap(long = "tag")]
+        tags: Vec<String>,
     },
     /// List all workspaces
-    List,
-    /// Delete a workspace or clear all workspaces
+    List {
+        /// Order the workspaces instead of listing them in registry order
+        #[clap(long = "sort", value_enum)]
+        sort: Option<ListSort>,
+
+        /// Only list workspaces with this tag (repeat to require several)
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+
+        /// Only list workspaces whose name matches this glob, e.g. "api-*"
+        #[clap(long = "name")]
+        name: Option<String>,
+
+        /// Only list workspaces in this directory
+        #[clap(long = "path")]
+        path: Option<std::path::PathBuf>,
+
+        /// Only list workspaces using this shell
+        #[clap(long = "shell")]
+        shell: Option<String>,
+
+        /// Only list workspaces with an init command containing this text
+        #[clap(long = "command")]
+        command: Option<String>,
+
+        /// How to print the workspaces
+        #[clap(long = "format", value_enum, default_value_t = ListFormat::Text)]
+        format: ListFormat,
+
+        /// Only print the names, one per line
+        #[clap(long = "names-only", conflicts_with = "format")]
+        names_only: bool,
+    },
+    /// Move a workspace, or all workspaces, to the trash
     Delete {
-        /// Name of the workspace to clear
+        /// Name of the workspace to delete
         name: Option<String>,
 
+        /// Delete every workspace with this tag (repeat to require several)
+        #[clap(long = "tag", conflicts_with = "name")]
+        tags: Vec<String>,
+
+        /// Don't ask before deleting workspaces by tag
         #[clap(short = 'y', long = "yes")]
         confirm: bool,
     },
-    /// Modifies a workspace, changing its attributes
+    /// List, restore or purge deleted workspaces
+    Trash {
+        #[clap(subcommand)]
+        command: TrashCommand,
+    },
+    /// Modifies a workspace, changing only the given attributes
+    ///
+    /// Init command positions start at 1. Removals refer to the commands as they were, then
+    /// insertions, moves and appends are applied in that order.
     Modify {
         /// The name of the workspace to modify
         name: String,
 
-        /// The path to the workspace (this will be the directory that will be cd'd into)
-        path: std::path::PathBuf,
+        /// Kept for compatibility, use --path
+        #[clap(hide = true, conflicts_with = "path")]
+        legacy_path: Option<std::path::PathBuf>,
 
-        /// The shell to execute
-        #[clap(short = 's', long = "shell")]
-        shell: Option<String>,
+        #[clap(flatten)]
+        changes: ModifyArgs,
+    },
+    /// Shows one workspace in detail
+    Show {
+        /// The name of the workspace to show
+        name: String,
+    },
+    /// Opens a workspace definition in $VISUAL or $EDITOR and saves the edited version
+    Edit {
+        /// The name of the workspace to edit
+        name: String,
+    },
+    /// Renames a workspace, keeping its history and usage
+    Rename {
+        /// The current name of the workspace
+        name: String,
 
-        /// The init commands in text form
-        #[clap(short = 't', long = "text")]
-        text: Option<String>,
+        /// The new name
+        new_name: String,
+    },
+    /// Copies a workspace under a new name, changing the given attributes of the copy
+    Duplicate {
+        /// The name of the workspace to copy
+        name: String,
 
-        /// The init commands in file form
-        #[clap(short = 'c', long = "cmd-path")]
-        command_path: Option<std::path::PathBuf>,
+        /// The name of the copy
+        new_name: String,
+
+        #[clap(flatten)]
+        changes: ModifyArgs,
     },
     /// => init
-    Run { name: String },
+    Run { name: Option<String> },
+    /// Runs the init commands of every workspace with the given tags, without opening shells
+    Up {
+        /// Only bring up workspaces with this tag (repeat to require several)
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Jumps to the registered workspace best matching part of its name or path, ranked by
+    /// how often and how recently it was used
+    J {
+        /// Parts of the name or path, all of which have to match
+        #[clap(required = true)]
+        query: Vec<String>,
+    },
+    /// Reverts the last changes to the registry
+    Undo {
+        /// How many changes to revert
+        #[clap(default_value_t = 1)]
+        count: usize,
+    },
+    /// Lists the recorded changes to the registry, newest first
+    History {
+        /// How many changes to show
+        #[clap(short = 'n', long = "limit", default_value_t = 20)]
+        limit: usize,
+    },
+    /// Checks the registry for malformed, corrupted or ambiguous entries and repairs them
+    Fsck {
+        /// The registry file to check (defaults to the configured workspaces file)
+        file: Option<std::path::PathBuf>,
+
+        /// Apply every repair without asking
+        #[clap(long = "fix")]
+        fix: bool,
+    },
+    /// List, create or switch between profiles, each with its own registry
+    Profile {
+        #[clap(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Copies the workspace defined by the nearest .workspace.toml into the registry
+    Register,
+    /// Imports the workspaces of a registry file (TOML or the legacy text format)
+    Import {
+        /// The registry file to import
+        file: std::path::PathBuf,
+    },
     // Config(fn(Config) -> CommandReturn),
 }
 
+/// The changes `ws modify` and `ws duplicate` make, leaving everything else as it is. Init
+/// command positions start at 1.
+#[derive(Debug, Args, Clone)]
+pub struct ModifyArgs {
+    /// The path to the workspace (this will be the directory that will be cd'd into)
+    #[clap(long = "path")]
+    pub path: Option<std::path::PathBuf>,
+
+    /// The shell to execute
+    #[clap(short = 's', long = "shell")]
+    pub shell: Option<String>,
+
+    /// Replace the init commands with these, separated by semicolons
+    #[clap(short = 't', long = "text", conflicts_with = "command_path")]
+    pub text: Option<String>,
+
+    /// Replace the init commands with this file
+    #[clap(short = 'c', long = "cmd-path")]
+    pub command_path: Option<std::path::PathBuf>,
+
+    /// Remove the init command at a position, counted before any is removed
+    #[clap(long = "remove-cmd", value_name = "POSITION")]
+    pub remove_commands: Vec<usize>,
+
+    /// Insert an init command at a position
+    #[clap(long = "insert-cmd", num_args = 2, value_names = ["POSITION", "COMMAND"])]
+    pub insert_commands: Vec<String>,
+
+    /// Move the init command at one position to another
+    #[clap(long = "move-cmd", num_args = 2, value_names = ["FROM", "TO"])]
+    pub move_commands: Vec<usize>,
+
+    /// Append an init command
+    #[clap(long = "add-cmd", value_name = "COMMAND")]
+    pub add_commands: Vec<String>,
+
+    /// Set an environment variable for the init commands and the shell
+    #[clap(long = "set-env", value_name = "NAME=VALUE")]
+    pub set_env: Vec<String>,
+
+    /// Remove an environment variable
+    #[clap(long = "unset-env", value_name = "NAME")]
+    pub unset_env: Vec<String>,
+
+    /// Replace the tags of the workspace (repeat for several)
+    #[clap(long = "tag")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ListSort {
+    /// By name
+    Name,
+    /// Most recently used first
+    Recent,
+    /// Most launched first
+    Launches,
+    /// Most recently created first
+    Created,
+    /// By stored path
+    Path,
+    /// By shell, then name
+    Shell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ListFormat {
+    /// A sentence per workspace
+    Text,
+    /// Aligned columns
+    Table,
+    /// An array of objects
+    Json,
+    /// Tab-separated values with a header line
+    Tsv,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum TrashCommand {
+    /// List the deleted workspaces
+    List,
+    /// Restore a deleted workspace
+    Restore {
+        /// Name of the workspace to restore
+        name: String,
+    },
+    /// Permanently delete workspaces that have been in the trash for a while
+    Purge {
+        /// Only purge workspaces deleted longer ago than this, e.g. "7days" (defaults to the
+        /// trash_retention setting)
+        #[clap(long = "older-than")]
+        older_than: Option<String>,
+
+        /// Purge every deleted workspace
+        #[clap(long = "all", conflicts_with = "older_than")]
+        all: bool,
+
+        #[clap(short = 'y', long = "yes")]
+        confirm: bool,
+    },
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum ProfileCommand {
+    /// List the profiles, marking the one in use
+    List,
+    /// Create a profile with an empty registry
+    Create {
+        /// Name of the profile to create
+        name: String,
+
+        /// The registry file of the profile (defaults to a file in the save directory)
+        #[clap(long = "file")]
+        file: Option<std::path::PathBuf>,
+    },
+    /// Make a profile the one used when none is selected
+    Switch {
+        /// Name of the profile to switch to
+        name: String,
+    },
+}
+
 impl Display for Command {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Command::Init { .. } => write!(f, "init"),
             Command::Add { .. } => write!(f, "add"),
             Command::List { .. } => write!(f, "list"),
-            Command::Delete { .. } => write!(f, "clear"),
+            Command::Delete { .. } => write!(f, "delete"),
+            Command::Trash { .. } => write!(f, "trash"),
             Command::Modify { .. } => write!(f, "modify"),
+            Command::Show { .. } => write!(f, "show"),
+            Command::Edit { .. } => write!(f, "edit"),
+            Command::Rename { .. } => write!(f, "rename"),
+            Command::Duplicate { .. } => write!(f, "duplicate"),
             Command::Run { .. } => write!(f, "run"),
+            Command::Up { .. } => write!(f, "up"),
+            Command::J { .. } => write!(f, "j"),
+            Command::Undo { .. } => write!(f, "undo"),
+            Command::History { .. } => write!(f, "history"),
+            Command::Fsck { .. } => write!(f, "fsck"),
+            Command::Profile { .. } => write!(f, "profile"),
+            Command::Register => write!(f, "register"),
+            Command::Import { .. } => write!(f, "import"),
             // Command::Config(_) => write!(f, "config"),
         }
     }
 }
 
 impl Command {
-    pub fn run(workspaces: Workspaces, command: Command) -> CommandReturn {
+    /// The workspace a command acts on by name alone, which is looked up on its own instead of
+    /// loading the whole registry
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Command::Init {
+                name: Some(name), ..
+            }
+            | Command::Run { name: Some(name) }
+            | Command::Show { name }
+            | Command::Modify { name, .. }
+            | Command::Edit { name }
+            | Command::Rename { name, .. } => Some(name),
+            Command::Delete {
+                name: Some(name),
+                tags,
+                ..
+            } if tags.is_empty() => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn run(
+        workspaces: Workspaces,
+        file_config: &FileConfig,
+        command: Command,
+    ) -> CommandReturn {
         match command {
-            Command::Init { name } => init(workspaces, name),
+            Command::Init { name, tags } => init(workspaces, name, tags),
             Command::Add {
                 name,
                 path,
                 shell,
                 text,
                 command_path,
-            } => add(workspaces, name, path, shell, text, command_path),
-            Command::List {} => list(workspaces),
-            Command::Delete { name, confirm } => delete(workspaces, name, confirm),
-            Command::Run { name } => init(workspaces, name),
-            Command::Modify {
+                tags,
+            } => add(workspaces, name, path, shell, text, command_path, tags),
+            Command::List {
+                sort,
+                tags,
                 name,
                 path,
                 shell,
-                text,
-                command_path,
-            } => modify(workspaces, name, path, shell, text, command_path),
+                command,
+                format,
+                names_only,
+            } => {
+                let filter = Filter {
+                    name,
+                    path,
+                    shell,
+                    command,
+                    tags,
+                };
+                list(workspaces, filter, sort, format, names_only)
+            }
+            Command::Delete {
+                name,
+                tags,
+                confirm,
+            } => delete(workspaces, name, tags, confirm),
+            Command::Trash { command } => match command {
+                TrashCommand::List => trash_list(workspaces),
+                TrashCommand::Restore { name } => trash_restore(workspaces, name),
+                TrashCommand::Purge {
+                    older_than,
+                    all,
+                    confirm,
+                } => trash_purge(
+                    workspaces,
+                    older_than,
+                    all,
+                    confirm,
+                    &file_config.trash_retention,
+                ),
+            },
+            Command::Run { name } => init(workspaces, name, Vec::new()),
+            Command::Up { tags } => up(workspaces, tags),
+            Command::J { query } => jump(workspaces, query),
+            Command::Undo { count } => undo(workspaces, count),
+            Command::History { limit } => history(workspaces, limit),
+            command @ (Command::Fsck { .. } | Command::Profile { .. }) => {
+                Command::run_standalone(file_config, command)
+            }
+            Command::Register => register(workspaces),
+            Command::Import { file } => import(workspaces, file),
+            Command::Modify {
+                name,
+                legacy_path,
+                mut changes,
+            } => {
+                changes.path = changes.path.or(legacy_path);
+                modify(workspaces, name, changes)
+            }
+            Command::Show { name } => show(workspaces, name),
+            Command::Edit { name } => edit(workspaces, name),
+            Command::Rename { name, new_name } => rename(workspaces, name, new_name),
+            Command::Duplicate {
+                name,
+                new_name,
+                changes,
+            } => duplicate(workspaces, name, new_name, changes),
             // Command::Config(set_config) => set_config(config),
         }
     }
 
+    /// Runs the commands that don't load the registry, so they keep working when it is broken
+    pub fn run_standalone(file_config: &FileConfig, command: Command) -> CommandReturn {
+        match command {
+            Command::Fsck { file, fix } => {
+                let file = match file {
+                    Some(file) => file,
+                    None => match file_config.current_profile() {
+                        Ok(profile) => profile.workspaces_file,
+                        Err(err) => return err.into(),
+                    },
+                };
+                fsck(&file, fix)
+            }
+            Command::Profile { command } => match command {
+                ProfileCommand::List => profile_list(file_config),
+                ProfileCommand::Create { name, file } => profile_create(file_config, name, file),
+                ProfileCommand::Switch { name } => profile_switch(file_config, name),
+            },
+            command => format!("{} needs the registry", command).into(),
+        }
+    }
+
     // pub fn run_string(command: String) -> CommandReturn {
     //     let config = Config::new(command.into());
     //
@@ -1,25 +1,33 @@
-use crate::Command;
+use crate::{output::say, paths, paths::Remap, registry::write_atomically, Command, RegistryLock};
 use clap::Parser;
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
+    fmt,
     fs::{self, File},
     io::Write,
-    path::Path,
+    path::{Path, PathBuf},
 };
+use toml_edit::{Document, Item, Table};
+
+/// The profile backed by the top-level `workspaces_file` and `database_file`
+pub const DEFAULT_PROFILE: &str = "default";
 
 pub enum EnvVar {
     SaveDir,
     WorkspacesFile,
     TestWorkspacesFile,
+    Profile,
 }
 
-impl ToString for EnvVar {
-    fn to_string(&self) -> String {
+impl fmt::Display for EnvVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            EnvVar::SaveDir => "SAVE_DIR".to_string(),
-            EnvVar::WorkspacesFile => "WORKSPACES_FILE".to_string(),
-            EnvVar::TestWorkspacesFile => "TEST_WORKSPACES_FILE".to_string(),
+            EnvVar::SaveDir => write!(f, "SAVE_DIR"),
+            EnvVar::WorkspacesFile => write!(f, "WORKSPACES_FILE"),
+            EnvVar::TestWorkspacesFile => write!(f, "TEST_WORKSPACES_FILE"),
+            EnvVar::Profile => write!(f, "WS_PROFILE"),
         }
     }
 }
@@ -38,6 +46,14 @@ pub struct Config {
     /// Path of the file to run (used with the add command to use the init commands from a file)
     pub path: Option<std::path::PathBuf>,
 
+    #[clap(long = "profile", global = true)]
+    /// The profile whose registry to use (defaults to WS_PROFILE, then the active profile)
+    pub profile: Option<String>,
+
+    #[clap(long = "json", global = true)]
+    /// Print a single JSON document with the result or error instead of text
+    pub json: bool,
+
     // TODO
     pub args: Option<Vec<String>>,
 }
@@ -48,6 +64,8 @@ impl Clone for Config {
             command: self.command.clone(),
             name: self.name.clone(),
             path: self.path.clone(),
+            profile: self.profile.clone(),
+            json: self.json,
             args: self.args.clone(),
         }
     }
@@ -55,61 +73,229 @@ impl Clone for Config {
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct FileConfig {
-    pub save_dir: String,
-    pub workspaces_file: String,
-    pub test_workspaces_file: String,
+    #[serde(with = "paths::os_string")]
+    pub save_dir: PathBuf,
+    #[serde(with = "paths::os_string")]
+    pub workspaces_file: PathBuf,
+    #[serde(with = "paths::os_string")]
+    pub test_workspaces_file: PathBuf,
+    /// Keep the registry in this SQLite database instead of `workspaces_file` (requires the
+    /// `sqlite` feature)
+    #[serde(default, with = "paths::option_os_string")]
+    pub database_file: Option<PathBuf>,
+    /// Read-only registries, such as a shared team catalog, merged with the personal one.
+    /// Workspaces in the personal registry take precedence over included ones with the same name.
+    #[serde(default, with = "paths::os_strings")]
+    pub includes: Vec<PathBuf>,
+    /// How long deleted workspaces stay in the trash before `ws trash purge` removes them,
+    /// e.g. "30days"
+    #[serde(default = "default_trash_retention")]
+    pub trash_retention: String,
+    /// The profile used when none is selected, set by `ws profile switch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// The profile selected for this invocation with `--profile` or WS_PROFILE
+    #[serde(skip)]
+    pub selected_profile: Option<String>,
+    /// Path prefixes to rewrite on particular machines, keyed by hostname, so one synced
+    /// registry works on all of them, e.g. `[remaps.buildbox]` with
+    /// `"/Users/me/code" = "/home/me/src"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub remaps: BTreeMap<String, BTreeMap<String, String>>,
+    /// Named registries besides the default one, e.g. `[profiles.work]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named registry, kept apart from the others along with its trash and history
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Profile {
+    #[serde(with = "paths::os_string")]
+    pub workspaces_file: PathBuf,
+    /// Keep this profile's registry in a SQLite database instead (requires the `sqlite` feature)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "paths::option_os_string"
+    )]
+    pub database_file: Option<PathBuf>,
+}
+
+fn default_trash_retention() -> String {
+    "30days".to_string()
 }
 
 // General environment functions
 impl FileConfig {
     pub fn build() -> Result<Self, String> {
-        if let Some(user_dir) = ProjectDirs::from("dev", "ws", "ws") {
-            let config_dir = user_dir.config_dir();
-            fs::create_dir_all(&config_dir).unwrap();
-
-            println!("Config dir: {:?}", config_dir);
-
-            let config_file: FileConfig = fs::read_to_string(config_dir.join("configuration.toml"))
-                .map(|file| toml::from_str(&file).unwrap())
-                .unwrap_or_else(|_| {
-                    let config_file_path = config_dir.join("configuration.toml");
-                    let file = File::create(&config_file_path)
-                        .map(|mut file| {
-                            let config_file = FileConfig {
-                                save_dir: config_dir.to_str().unwrap().to_string(),
-                                workspaces_file: config_dir
-                                    .join("workspaces.txt")
-                                    .to_str()
-                                    .unwrap()
-                                    .to_string(),
-                                test_workspaces_file: config_dir
-                                    .join("test_workspaces.txt")
-                                    .to_str()
-                                    .unwrap()
-                                    .to_string(),
-                            };
-
-                            let config_file_toml = toml::to_string(&config_file).unwrap();
-                            println!("Config file: {:?}", config_file_toml);
-                            file.write_all(config_file_toml.as_bytes()).unwrap();
-
-                            println!("Created config file at {:?}", config_file_path);
-                            return config_file;
-                        })
-                        .expect("Could not create config file");
-                    file
-                });
-
-            if !Path::new(&config_file.save_dir).is_dir() {
-                match fs::create_dir_all(&config_file.save_dir) {
-                    Ok(_) => {}
-                    Err(e) => return Err(format!("{:?}", e)),
-                };
+        let user_dir = ProjectDirs::from("dev", "ws", "ws")
+            .ok_or_else(|| "Could not find project directories".to_string())?;
+        let config_dir = user_dir.config_dir();
+        fs::create_dir_all(config_dir)
+            .map_err(|e| format!("Could not create {}: {}", config_dir.display(), e))?;
+
+        let config_file_path = config_dir.join("configuration.toml");
+        let config_file: FileConfig = match fs::read_to_string(&config_file_path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Could not parse {}: {}", config_file_path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                FileConfig::create(config_dir, &config_file_path)?
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Could not read {}: {}",
+                    config_file_path.display(),
+                    e
+                ))
+            }
+        };
+
+        if !config_file.save_dir.is_dir() {
+            match fs::create_dir_all(&config_file.save_dir) {
+                Ok(_) => {}
+                Err(e) => return Err(format!("{:?}", e)),
+            };
+        }
+
+        Ok(config_file)
+    }
+
+    /// Writes the default configuration to `config_file_path`, keeping everything in
+    /// `config_dir`
+    fn create(config_dir: &Path, config_file_path: &Path) -> Result<FileConfig, String> {
+        let config_file = FileConfig {
+            save_dir: config_dir.to_path_buf(),
+            workspaces_file: config_dir.join("workspaces.toml"),
+            test_workspaces_file: config_dir.join("test_workspaces.toml"),
+            database_file: None,
+            includes: Vec::new(),
+            trash_retention: default_trash_retention(),
+            profile: None,
+            selected_profile: None,
+            remaps: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+        };
+
+        let config_file_toml = toml::to_string(&config_file)
+            .map_err(|e| format!("Could not serialize the configuration: {}", e))?;
+        File::create(config_file_path)
+            .and_then(|mut file| file.write_all(config_file_toml.as_bytes()))
+            .map_err(|e| format!("Could not create {}: {}", config_file_path.display(), e))?;
+
+        say!("Created config file at {:?}", config_file_path);
+        Ok(config_file)
+    }
+}
+
+// Profile functions
+impl FileConfig {
+    pub fn config_file_path() -> Result<PathBuf, String> {
+        ProjectDirs::from("dev", "ws", "ws")
+            .map(|user_dir| user_dir.config_dir().join("configuration.toml"))
+            .ok_or_else(|| "Could not find project directories".to_string())
+    }
+
+    /// Adds `profile` to the configuration file at `file_path`
+    pub fn add_profile(file_path: &Path, name: &str, profile: &Profile) -> Result<(), String> {
+        let definition = toml::to_string(profile)
+            .map_err(|e| format!("Could not serialize profile {}: {}", name, e))?;
+        let definition: Document = definition
+            .parse()
+            .map_err(|e| format!("Could not serialize profile {}: {}", name, e))?;
+
+        edit(file_path, |document| {
+            if !document.contains_key("profiles") {
+                let mut profiles = Table::new();
+                profiles.set_implicit(true);
+                document.insert("profiles", Item::Table(profiles));
             }
+            match document["profiles"].as_table_mut() {
+                Some(profiles) => {
+                    profiles.insert(name, Item::Table(definition.as_table().clone()));
+                    Ok(())
+                }
+                None => Err("profiles is not a table".to_string()),
+            }
+        })
+    }
+
+    /// Sets the active profile in the configuration file at `file_path`, `None` being the default
+    pub fn set_profile(file_path: &Path, name: Option<&str>) -> Result<(), String> {
+        edit(file_path, |document| {
+            match name {
+                Some(name) => document.insert("profile", toml_edit::value(name)),
+                None => document.remove("profile"),
+            };
+            Ok(())
+        })
+    }
+
+    /// The name of the profile in use: the selected one, then the active one, then the default
+    pub fn profile_name(&self) -> String {
+        self.selected_profile
+            .clone()
+            .or_else(|| self.profile.clone())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    pub fn find_profile(&self, name: &str) -> Option<Profile> {
+        if name == DEFAULT_PROFILE {
+            return Some(Profile {
+                workspaces_file: self.workspaces_file.clone(),
+                database_file: self.database_file.clone(),
+            });
+        }
+        self.profiles.get(name).cloned()
+    }
+
+    /// The profile in use
+    pub fn current_profile(&self) -> Result<Profile, String> {
+        let name = self.profile_name();
+        self.find_profile(&name).ok_or_else(|| {
+            format!(
+                "Unknown profile {}, create it with `ws profile create {}`",
+                name, name
+            )
+        })
+    }
+
+    /// The path remaps for the machine called `hostname`
+    pub fn remaps_for(&self, hostname: &str) -> Vec<Remap> {
+        self.remaps
+            .iter()
+            .filter(|(host, _)| host.eq_ignore_ascii_case(hostname))
+            .flat_map(|(_, remaps)| remaps)
+            .map(|(from, to)| Remap {
+                from: from.into(),
+                to: to.into(),
+            })
+            .collect()
+    }
 
-            return Ok(config_file);
+    /// Each profile has its own history, so undo never reverts another profile's registry
+    pub fn journal_file(&self) -> PathBuf {
+        let name = self.profile_name();
+        if name == DEFAULT_PROFILE {
+            self.save_dir.join("journal.toml")
         } else {
-            return Err("Could not find project directories".to_string());
+            self.save_dir.join(format!("journal-{}.toml", name))
         }
     }
 }
+
+/// Applies `modify` to the configuration file at `file_path` under its lock, keeping the user's
+/// comments and layout, and replaces it atomically
+fn edit<F>(file_path: &Path, modify: F) -> Result<(), String>
+where
+    F: FnOnce(&mut Document) -> Result<(), String>,
+{
+    let _lock = RegistryLock::acquire(file_path)?;
+    let contents = fs::read_to_string(file_path)
+        .map_err(|e| format!("Could not read {}: {}", file_path.display(), e))?;
+    let mut document: Document = contents
+        .parse()
+        .map_err(|e| format!("Could not parse {}: {}", file_path.display(), e))?;
+    modify(&mut document)
+        .map_err(|e| format!("Could not update {}: {}", file_path.display(), e))?;
+    write_atomically(file_path, &document.to_string())
+}
@@ -0,0 +1,94 @@
+use crate::{is_valid_tag, paths, Workspace};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+const HEADER: &str = "\
+# Save and close the editor to apply the changes, or delete everything to cancel.
+# init_commands run in order, one command per line.
+
+";
+
+/// The fields of a workspace that can be changed with `ws edit`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    #[serde(with = "paths::os_string")]
+    path: PathBuf,
+    shell: String,
+    #[serde(default, with = "paths::os_strings")]
+    init_commands: Vec<OsString>,
+    #[serde(default)]
+    tags: Vec<String>,
+    // Tables come last in TOML
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+/// Writes the editable fields of `workspace` as commented TOML
+pub fn to_toml(workspace: &Workspace) -> Result<String, String> {
+    let definition = Definition {
+        name: workspace.name.clone(),
+        path: workspace.path.clone(),
+        shell: workspace.shell.clone(),
+        init_commands: workspace.init_commands.clone(),
+        tags: workspace.tags.clone(),
+        env: workspace.env.clone(),
+    };
+    toml::to_string_pretty(&definition)
+        .map(|toml| format!("{}{}", HEADER, toml))
+        .map_err(|e| format!("Could not write {}: {}", workspace.name, e))
+}
+
+/// Reads an edited definition back into a copy of `original`, resolving a relative path
+/// against `base`. Returns `None` if everything but comments was deleted.
+pub fn parse(
+    contents: &str,
+    original: &Workspace,
+    base: &Path,
+) -> Result<Option<Workspace>, String> {
+    let is_blank = contents
+        .lines()
+        .all(|line| line.trim().is_empty() || line.trim_start().starts_with('#'));
+    if is_blank {
+        return Ok(None);
+    }
+
+    let definition: Definition = toml::from_str(contents).map_err(|e| e.to_string())?;
+    if definition.name.trim().is_empty() {
+        return Err("name can't be empty".to_string());
+    }
+    if definition.path.as_os_str().is_empty() {
+        return Err("path can't be empty".to_string());
+    }
+    if definition.shell.trim().is_empty() {
+        return Err("shell can't be empty".to_string());
+    }
+    if let Some(tag) = definition.tags.iter().find(|tag| !is_valid_tag(tag)) {
+        return Err(format!(
+            "invalid tag {:?}, tags can't be empty or contain commas or spaces",
+            tag
+        ));
+    }
+    if let Some(name) = definition
+        .env
+        .keys()
+        .find(|name| name.is_empty() || name.contains('='))
+    {
+        return Err(format!("invalid environment variable name {:?}", name));
+    }
+
+    Ok(Some(Workspace {
+        name: definition.name,
+        path: paths::normalize(&definition.path, base),
+        shell: definition.shell,
+        init_commands: definition.init_commands,
+        tags: definition.tags,
+        env: definition.env,
+        ..original.clone()
+    }))
+}
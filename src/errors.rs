@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::{fmt, error::Error};
 
 pub struct CommandReturn(pub Result<(), WorkspaceError>);
@@ -26,23 +27,57 @@ impl From<()> for CommandReturn {
     }
 }
 
-impl Into<Result<(), WorkspaceError>> for CommandReturn {
-    fn into(self) -> Result<(), WorkspaceError> {
-        self.0
+impl From<Result<(), WorkspaceError>> for CommandReturn {
+    fn from(result: Result<(), WorkspaceError>) -> Self {
+        CommandReturn(result)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl From<CommandReturn> for Result<(), WorkspaceError> {
+    fn from(command_return: CommandReturn) -> Self {
+        command_return.0
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Severity {
     Error,
     Warning,
     Message
 }
 
-#[derive(Debug)]
+/// A stable name for the kind of an error, so tools reading `--json` output don't have to
+/// match on messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// No more specific code applies
+    Other,
+    NotFound,
+    AlreadyExists,
+    InvalidArgument,
+    /// A query or name matches more than one workspace
+    Ambiguous,
+    /// The user declined a confirmation or picked nothing
+    Cancelled,
+    /// The command would ask for confirmation, which `--json` can't, so `--yes` is needed
+    ConfirmationRequired,
+    /// There is nothing to act on, such as an empty registry or trash
+    Empty,
+    /// The workspace comes from an included registry
+    ReadOnly,
+    Io,
+    /// The registry, journal or configuration can't be read or is inconsistent
+    InvalidRegistry,
+    Config,
+}
+
+#[derive(Debug, Serialize)]
 pub struct WorkspaceError {
     pub message: String,
     pub severity: Severity,
+    pub code: ErrorCode,
 }
 
 impl WorkspaceError {
@@ -50,8 +85,25 @@ impl WorkspaceError {
         Self {
             message,
             severity,
+            code: ErrorCode::Other,
         }
     }
+
+    /// An error with a code other than `ErrorCode::Other`
+    pub fn coded(code: ErrorCode, message: String) -> Self {
+        Self::new(message, Severity::Error).with_code(code)
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+}
+
+impl From<String> for WorkspaceError {
+    fn from(message: String) -> Self {
+        WorkspaceError::new(message, Severity::Error)
+    }
 }
 
 impl fmt::Display for WorkspaceError {
@@ -0,0 +1,70 @@
+use crate::Workspace;
+use std::path::{Path, PathBuf};
+
+/// Criteria for `ws list`, all of which a workspace has to meet
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// A glob the name has to match, where `*` matches any run of characters and `?` any one
+    pub name: Option<String>,
+    /// A directory the stored or resolved path has to be in
+    pub path: Option<PathBuf>,
+    pub shell: Option<String>,
+    /// Text one of the init commands has to contain
+    pub command: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Filter {
+    /// Whether `workspace`, whose path resolves to `resolved` on this machine, meets every
+    /// criterion
+    pub fn matches(&self, workspace: &Workspace, resolved: &Path) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, &workspace.name))
+            && self.path.as_ref().is_none_or(|prefix| {
+                workspace.path.starts_with(prefix) || resolved.starts_with(prefix)
+            })
+            && self
+                .shell
+                .as_ref()
+                .is_none_or(|shell| workspace.shell == *shell)
+            && self.command.as_ref().is_none_or(|text| {
+                workspace
+                    .init_commands
+                    .iter()
+                    .any(|command| command.to_string_lossy().contains(text.as_str()))
+            })
+            && workspace.has_tags(&self.tags)
+    }
+}
+
+/// Matches `text` against a glob where `*` matches any run of characters and `?` any single one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Backtrack to the last `*` on a mismatch, which is enough without character classes
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
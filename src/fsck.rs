@@ -0,0 +1,332 @@
+use crate::{registry::is_legacy, Registry, Shell, TrashedWorkspace, Workspace, REGISTRY_VERSION};
+
+/// A workspace read from a registry file, with the line it starts on
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub line: usize,
+    pub workspace: Workspace,
+}
+
+/// How a problem can be repaired
+#[derive(Debug, Clone, PartialEq)]
+pub enum Repair {
+    /// Drop the entry
+    Remove,
+    /// Use the default shell for an entry whose shell is missing
+    DefaultShell,
+    /// Move the value in the shell field back to the front of the init commands and use the
+    /// default shell, undoing the old bug that saved entries without their shell
+    UnshiftShell,
+    /// Give the entry a unique name
+    Rename(String),
+}
+
+impl Repair {
+    pub fn describe(&self) -> String {
+        match self {
+            Repair::Remove => "remove the entry".to_string(),
+            Repair::DefaultShell => format!("use {}", Shell::default_name()),
+            Repair::UnshiftShell => format!(
+                "move it back to the init commands and use {}",
+                Shell::default_name()
+            ),
+            Repair::Rename(name) => format!("rename it to {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub line: usize,
+    pub message: String,
+    /// The entry the repair applies to
+    pub entry: Option<usize>,
+    pub repair: Option<Repair>,
+}
+
+/// The result of checking a registry file
+#[derive(Debug, Default)]
+pub struct Check {
+    pub legacy: bool,
+    pub entries: Vec<Entry>,
+    pub trash: Vec<TrashedWorkspace>,
+    pub issues: Vec<Issue>,
+}
+
+impl Check {
+    /// Whether the file could not be read well enough to rewrite it
+    pub fn is_unreadable(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.entry.is_none() && issue.repair.is_none())
+    }
+
+    /// Builds the registry with the repairs of the `accepted` issues applied
+    pub fn repair(&self, accepted: &[usize]) -> Registry {
+        let mut workspaces: Vec<Option<Workspace>> = self
+            .entries
+            .iter()
+            .map(|entry| Some(entry.workspace.clone()))
+            .collect();
+
+        for &index in accepted {
+            let issue = &self.issues[index];
+            let (entry, repair) = match (issue.entry, &issue.repair) {
+                (Some(entry), Some(repair)) => (entry, repair),
+                _ => continue,
+            };
+            let workspace = match &mut workspaces[entry] {
+                Some(workspace) => workspace,
+                None => continue,
+            };
+
+            match repair {
+                Repair::Remove => workspaces[entry] = None,
+                Repair::DefaultShell => workspace.shell = Shell::default_name().to_string(),
+                Repair::UnshiftShell => {
+                    let command =
+                        std::mem::replace(&mut workspace.shell, Shell::default_name().to_string());
+                    if !command.is_empty() {
+                        workspace.init_commands.insert(0, command.into());
+                    }
+                }
+                Repair::Rename(name) => workspace.name = name.clone(),
+            }
+        }
+
+        let mut registry = Registry {
+            version: REGISTRY_VERSION,
+            workspaces: workspaces.into_iter().flatten().collect(),
+            trash: self.trash.clone(),
+        };
+        registry.assign_ids();
+        registry
+    }
+}
+
+/// Checks the contents of a registry file in either format without ever failing, reporting
+/// malformed, corrupted and ambiguous entries with their line numbers
+pub fn check(contents: &str) -> Check {
+    let mut check = if is_legacy(contents) {
+        check_legacy(contents)
+    } else {
+        check_toml(contents)
+    };
+
+    check_entries(&mut check);
+    check
+}
+
+fn check_legacy(contents: &str) -> Check {
+    let mut check = Check {
+        legacy: true,
+        ..Check::default()
+    };
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut parts = line.split(';');
+        let name = parts.next().unwrap_or_default();
+        let path = parts.next();
+        let shell = parts.next();
+        let init_commands = parts
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+
+        let path = match path {
+            Some(path) => path,
+            None => {
+                check.issues.push(Issue {
+                    line: line_number,
+                    message: format!("expected a name, a path and a shell, found {:?}", line),
+                    entry: Some(check.entries.len()),
+                    repair: Some(Repair::Remove),
+                });
+                check.entries.push(Entry {
+                    line: line_number,
+                    workspace: Workspace::new(name.to_string(), "".into(), String::new(), vec![]),
+                });
+                continue;
+            }
+        };
+
+        if shell.is_none() {
+            check.issues.push(Issue {
+                line: line_number,
+                message: format!("workspace {} has no shell", name),
+                entry: Some(check.entries.len()),
+                repair: Some(Repair::DefaultShell),
+            });
+        }
+
+        check.entries.push(Entry {
+            line: line_number,
+            workspace: Workspace::new(
+                name.to_string(),
+                path.into(),
+                shell.unwrap_or_default().to_string(),
+                init_commands,
+            ),
+        });
+    }
+    check
+}
+
+fn check_toml(contents: &str) -> Check {
+    let registry = match Registry::from_toml(contents) {
+        Ok(registry) => registry,
+        Err(e) => {
+            return Check {
+                issues: vec![Issue {
+                    line: error_line(&e).unwrap_or(1),
+                    // The last line of a TOML error is the message, the rest shows the location
+                    message: e
+                        .lines()
+                        .rev()
+                        .find(|line| !line.trim().is_empty())
+                        .unwrap_or(&e)
+                        .trim()
+                        .to_string(),
+                    entry: None,
+                    repair: None,
+                }],
+                ..Check::default()
+            };
+        }
+    };
+
+    // Entries are serialized in order, each starting with its own table header
+    let lines: Vec<usize> = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim() == "[[workspaces]]")
+        .map(|(index, _)| index + 1)
+        .collect();
+
+    Check {
+        legacy: false,
+        entries: registry
+            .workspaces
+            .into_iter()
+            .enumerate()
+            .map(|(index, workspace)| Entry {
+                line: lines.get(index).copied().unwrap_or(1),
+                workspace,
+            })
+            .collect(),
+        trash: registry.trash,
+        issues: Vec::new(),
+    }
+}
+
+/// Reads the line number out of a TOML error such as "TOML parse error at line 3, column 1"
+fn error_line(error: &str) -> Option<usize> {
+    let rest = &error[error.find("line ")? + "line ".len()..];
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+fn check_entries(check: &mut Check) {
+    for (index, entry) in check.entries.iter().enumerate() {
+        let workspace = &entry.workspace;
+        if check.issues.iter().any(|issue| issue.entry == Some(index)) {
+            continue;
+        }
+
+        if workspace.name.trim().is_empty() {
+            check.issues.push(Issue {
+                line: entry.line,
+                message: "workspace has no name".to_string(),
+                entry: Some(index),
+                repair: Some(Repair::Remove),
+            });
+        } else if workspace.path.as_os_str().is_empty() {
+            check.issues.push(Issue {
+                line: entry.line,
+                message: format!("workspace {} has no path", workspace.name),
+                entry: Some(index),
+                repair: Some(Repair::Remove),
+            });
+        } else if workspace.shell.trim().is_empty() {
+            let repair = if workspace.init_commands.is_empty() {
+                Repair::DefaultShell
+            } else {
+                Repair::UnshiftShell
+            };
+            check.issues.push(Issue {
+                line: entry.line,
+                message: format!("workspace {} has no shell", workspace.name),
+                entry: Some(index),
+                repair: Some(repair),
+            });
+        } else if looks_like_command(&workspace.shell) {
+            check.issues.push(Issue {
+                line: entry.line,
+                message: format!(
+                    "workspace {} has {:?} as its shell, which looks like an init command saved in the shell field",
+                    workspace.name, workspace.shell
+                ),
+                entry: Some(index),
+                repair: Some(Repair::UnshiftShell),
+            });
+        }
+    }
+
+    // Lookups by name can't tell apart workspaces that share one
+    let mut names: Vec<String> = Vec::new();
+    let mut taken: Vec<String> = check
+        .entries
+        .iter()
+        .map(|e| e.workspace.name.clone())
+        .collect();
+    for (index, entry) in check.entries.iter().enumerate() {
+        let name = &entry.workspace.name;
+        if name.trim().is_empty() {
+            continue;
+        }
+
+        if names.contains(name) {
+            let first = check
+                .entries
+                .iter()
+                .find(|e| e.workspace.name == *name)
+                .map_or(1, |e| e.line);
+            let unique = unique_name(name, &taken);
+            taken.push(unique.clone());
+            check.issues.push(Issue {
+                line: entry.line,
+                message: format!(
+                    "workspace name {} is already used on line {}, which makes it ambiguous",
+                    name, first
+                ),
+                entry: Some(index),
+                repair: Some(Repair::Rename(unique)),
+            });
+        }
+        names.push(name.clone());
+    }
+
+    check.issues.sort_by_key(|issue| issue.line);
+}
+
+/// Commands have arguments or operators, and a single word that isn't a shell is a program like
+/// `make` rather than the shell to open
+fn looks_like_command(shell: &str) -> bool {
+    shell.contains(char::is_whitespace)
+        || shell
+            .chars()
+            .any(|c| matches!(c, ';' | '&' | '|' | '<' | '>' | '$' | '`' | '"' | '\''))
+        || !Shell::is_known(shell)
+}
+
+fn unique_name(name: &str, taken: &[String]) -> String {
+    (2..)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_else(|| name.to_string())
+}
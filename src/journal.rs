@@ -0,0 +1,274 @@
+use crate::{
+    registry::write_atomically, timestamps, Registry, RegistryLock, TrashedWorkspace, Workspace,
+    WorkspaceError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Once the journal has this many entries, the oldest are dropped so it doesn't grow forever
+const MAX_ENTRIES: usize = 500;
+
+/// How many entries are kept when the journal is compacted
+const KEPT_ENTRIES: usize = 400;
+
+/// One registry mutation, with enough state to revert it
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub description: String,
+    /// The entries this one reverted, if it is an undo
+    #[serde(default)]
+    pub reverts: Vec<u64>,
+    /// The registered workspaces the mutation touched, as they were before it. Entries written
+    /// before changes were recorded have `None` and a full snapshot instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Vec<Change>>,
+    /// The trashed workspaces the mutation added
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trashed: Vec<TrashKey>,
+    /// The trashed workspaces the mutation removed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub untrashed: Vec<TrashedWorkspace>,
+    /// The registered workspaces before the mutation, in entries without `changes`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<Workspace>,
+    /// The trash before the mutation, in entries without `changes`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash_before: Vec<TrashedWorkspace>,
+}
+
+/// A registered workspace touched by a mutation
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Change {
+    pub id: String,
+    /// Where the workspace was in the registry, or was added
+    pub position: usize,
+    /// The workspace before the mutation, `None` if it was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Workspace>,
+}
+
+/// Identifies a trashed workspace
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TrashKey {
+    pub id: String,
+    pub deleted_at: u64,
+}
+
+impl TrashKey {
+    fn of(trashed: &TrashedWorkspace) -> TrashKey {
+        TrashKey {
+            id: trashed.workspace.id.clone(),
+            deleted_at: trashed.deleted_at,
+        }
+    }
+}
+
+impl JournalEntry {
+    pub fn time(&self) -> SystemTime {
+        timestamps::to_system_time(self.timestamp)
+    }
+
+    pub fn is_undo(&self) -> bool {
+        !self.reverts.is_empty()
+    }
+
+    /// Reverts the mutation in `registry`, leaving whatever else changed since alone
+    pub fn revert(&self, registry: &mut Registry) {
+        let changes = match &self.changes {
+            Some(changes) => changes,
+            None => {
+                registry.workspaces = self.before.clone();
+                registry.trash = self.trash_before.clone();
+                return;
+            }
+        };
+
+        registry
+            .workspaces
+            .retain(|w| !changes.iter().any(|change| change.id == w.id));
+        let mut restored: Vec<&Change> = changes.iter().filter(|c| c.before.is_some()).collect();
+        restored.sort_by_key(|change| change.position);
+        for change in restored {
+            if let Some(before) = &change.before {
+                let position = change.position.min(registry.workspaces.len());
+                registry.workspaces.insert(position, before.clone());
+            }
+        }
+
+        registry
+            .trash
+            .retain(|trashed| !self.trashed.contains(&TrashKey::of(trashed)));
+        registry.trash.extend(self.untrashed.iter().cloned());
+        registry.trash.sort_by_key(|trashed| trashed.deleted_at);
+    }
+}
+
+/// The registered workspaces that differ between `before` and `after`, as they were before
+fn changes(before: &Registry, after: &Registry) -> Vec<Change> {
+    let same = |a: &Workspace, b: &Workspace| toml::to_string(a).ok() == toml::to_string(b).ok();
+    let mut changes: Vec<Change> = before
+        .workspaces
+        .iter()
+        .enumerate()
+        .filter(|(_, old)| !after.workspaces.iter().any(|new| same(old, new)))
+        .map(|(position, old)| Change {
+            id: old.id.clone(),
+            position,
+            before: Some(old.clone()),
+        })
+        .collect();
+    for (position, new) in after.workspaces.iter().enumerate() {
+        if !before.workspaces.iter().any(|old| old.id == new.id) {
+            changes.push(Change {
+                id: new.id.clone(),
+                position,
+                before: None,
+            });
+        }
+    }
+    changes
+}
+
+#[derive(Deserialize, Serialize, Default)]
+struct JournalFile {
+    #[serde(default)]
+    entries: Vec<JournalEntry>,
+}
+
+/// An append-only log of registry mutations.
+///
+/// Each entry is appended as its own `[[entries]]` table, so the file stays valid TOML without
+/// being rewritten until it is compacted.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    pub file_path: PathBuf,
+}
+
+/// Holds the journal lock, taken before the registry is updated so concurrent invocations
+/// journal their changes in the order they were applied
+pub struct JournalLock<'a> {
+    journal: &'a Journal,
+    _lock: RegistryLock,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(file_path: P) -> Journal {
+        Journal {
+            file_path: file_path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn lock(&self) -> Result<JournalLock<'_>, WorkspaceError> {
+        Ok(JournalLock {
+            journal: self,
+            _lock: RegistryLock::acquire(&self.file_path)?,
+        })
+    }
+
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, WorkspaceError> {
+        let contents = match fs::read_to_string(&self.file_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(
+                    format!("Could not read journal {}: {}", self.file_path.display(), e).into(),
+                )
+            }
+        };
+
+        let journal: JournalFile = toml::from_str(&contents).map_err(|e| {
+            format!(
+                "Could not parse journal {}: {}",
+                self.file_path.display(),
+                e
+            )
+        })?;
+        Ok(journal.entries)
+    }
+
+    /// The mutations that can still be undone, oldest first
+    pub fn undoable(&self) -> Result<Vec<JournalEntry>, WorkspaceError> {
+        let entries = self.entries()?;
+        let reverted: Vec<u64> = entries
+            .iter()
+            .flat_map(|entry| entry.reverts.iter().copied())
+            .collect();
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| !entry.is_undo() && !reverted.contains(&entry.sequence))
+            .collect())
+    }
+}
+
+impl JournalLock<'_> {
+    pub fn undoable(&self) -> Result<Vec<JournalEntry>, WorkspaceError> {
+        self.journal.undoable()
+    }
+
+    /// Records the mutation that turned `before` into `after`
+    pub fn append(
+        &self,
+        description: String,
+        before: &Registry,
+        after: &Registry,
+        reverts: Vec<u64>,
+    ) -> Result<JournalEntry, WorkspaceError> {
+        let file_path = &self.journal.file_path;
+        let write_error =
+            |e: std::io::Error| format!("Could not write journal {}: {}", file_path.display(), e);
+
+        let mut entries = self.journal.entries()?;
+        let entry = JournalEntry {
+            sequence: entries.last().map_or(1, |entry| entry.sequence + 1),
+            timestamp: timestamps::now(),
+            description,
+            reverts,
+            changes: Some(changes(before, after)),
+            trashed: after
+                .trash
+                .iter()
+                .filter(|trashed| !before.trash.contains(trashed))
+                .map(TrashKey::of)
+                .collect(),
+            untrashed: before
+                .trash
+                .iter()
+                .filter(|trashed| !after.trash.contains(trashed))
+                .cloned()
+                .collect(),
+            before: Vec::new(),
+            trash_before: Vec::new(),
+        };
+
+        if entries.len() >= MAX_ENTRIES {
+            entries.drain(..entries.len() + 1 - KEPT_ENTRIES);
+            entries.push(entry.clone());
+            let contents = toml::to_string(&JournalFile { entries })
+                .map_err(|e| format!("Could not serialize the journal: {}", e))?;
+            write_atomically(file_path, &contents)?;
+            return Ok(entry);
+        }
+
+        let contents = toml::to_string(&JournalFile {
+            entries: vec![entry.clone()],
+        })
+        .map_err(|e| format!("Could not serialize journal entry: {}", e))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)
+            .map_err(write_error)?;
+        file.write_all(format!("\n{}", contents).as_bytes())
+            .map_err(write_error)?;
+        Ok(entry)
+    }
+}
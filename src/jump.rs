@@ -0,0 +1,105 @@
+use crate::{Workspace, WorkspaceSource};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// A workspace matching a `ws j` query, with its score
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub score: f64,
+    pub workspace: &'a Workspace,
+}
+
+/// How often and how recently a workspace was used: launches count for more the more recent
+/// the last one was, as in zoxide
+pub fn frecency(workspace: &Workspace, now: u64) -> f64 {
+    let last_used_at = match workspace.last_used_at {
+        Some(last_used_at) => last_used_at,
+        None => return 0.0,
+    };
+
+    let age = now.saturating_sub(last_used_at);
+    let weight = if age < HOUR {
+        4.0
+    } else if age < DAY {
+        2.0
+    } else if age < WEEK {
+        0.5
+    } else {
+        0.25
+    };
+    workspace.launch_count as f64 * weight
+}
+
+/// Scores how well every term of `query` matches the workspace name or path, or `None` if one
+/// doesn't match at all. Matches on the name score higher than matches on the path.
+pub fn match_score(query: &[String], workspace: &Workspace) -> Option<f64> {
+    let name = workspace.name.to_lowercase();
+    let path = workspace.path.to_string_lossy().to_lowercase();
+
+    query.iter().try_fold(0.0, |total, term| {
+        let term = term.to_lowercase();
+        let score = if name == term {
+            100.0
+        } else if name.starts_with(&term) {
+            60.0
+        } else if name.contains(&term) {
+            40.0
+        } else if path.contains(&term) {
+            20.0
+        } else if is_subsequence(&term, &name) {
+            10.0
+        } else {
+            return None;
+        };
+        Some(total + score)
+    })
+}
+
+/// The registered workspaces matching `query`, best first, weighing the match by frecency
+pub fn rank<'a>(query: &[String], workspaces: &'a [Workspace], now: u64) -> Vec<Candidate<'a>> {
+    let mut candidates: Vec<Candidate> = workspaces
+        .iter()
+        .filter(|w| w.source == WorkspaceSource::Registry)
+        .filter_map(|workspace| {
+            match_score(query, workspace).map(|score| Candidate {
+                // Logarithmic, so frecency can't make up for a much weaker match
+                score: score * (1.0 + frecency(workspace, now).ln_1p()),
+                workspace,
+            })
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.workspace.name.cmp(&b.workspace.name))
+    });
+    candidates
+}
+
+/// The candidate to jump to: the only one, an exact name match, or one scoring at least twice
+/// as high as the runner-up
+pub fn best<'a>(query: &[String], candidates: &[Candidate<'a>]) -> Option<&'a Workspace> {
+    let first = candidates.first()?;
+    if let [term] = query {
+        if let Some(exact) = candidates
+            .iter()
+            .find(|c| c.workspace.name.eq_ignore_ascii_case(term))
+        {
+            return Some(exact.workspace);
+        }
+    }
+
+    match candidates.get(1) {
+        Some(second) if first.score < second.score * 2.0 => None,
+        _ => Some(first.workspace),
+    }
+}
+
+/// Whether the characters of `needle` appear in `haystack` in order
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
@@ -1,28 +1,117 @@
 pub use crate::commands::*;
 pub use crate::configuration::*;
 pub use crate::errors::*;
+pub use crate::fsck::{Check, Issue, Repair};
+pub use crate::journal::*;
+pub use crate::local::*;
+pub use crate::registry::*;
 pub use crate::shell::*;
+#[cfg(feature = "sqlite")]
+pub use crate::sqlite_store::*;
+pub use crate::store::*;
 pub use crate::workspaces::*;
 
 pub mod commands;
 pub mod configuration;
+pub mod edit;
 pub mod errors;
+pub mod filter;
+pub mod fsck;
+pub mod journal;
+pub mod jump;
+pub mod local;
+pub mod output;
+pub mod patch;
+pub mod paths;
+pub mod picker;
+pub mod registry;
 pub mod shell;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod store;
+pub mod timestamps;
 
 // Set up general tests
 #[cfg(test)]
 mod tests;
+pub mod wizard;
 pub mod workspaces;
 
 pub fn run(config: Config) -> Result<(), WorkspaceError> {
-    let file_config = match FileConfig::build() {
+    let mut file_config = match FileConfig::build() {
         Ok(val) => val,
-        Err(err) => return Err(WorkspaceError::new(err, Severity::Error)),
+        Err(err) => return Err(WorkspaceError::coded(ErrorCode::Config, err)),
     };
+    file_config.selected_profile = config.profile.clone().or_else(|| {
+        std::env::var(EnvVar::Profile.to_string())
+            .ok()
+            .filter(|profile| !profile.is_empty())
+    });
+
+    // fsck and profile commands have to work on registries that can't be loaded
+    if let Command::Fsck { .. } | Command::Profile { .. } = config.command {
+        return Command::run_standalone(&file_config, config.command).into();
+    }
 
     // Initialize workspaces
-    let workspaces = Workspaces::new(file_config.workspaces_file.as_str());
+    let profile = file_config
+        .current_profile()
+        .map_err(|e| WorkspaceError::coded(ErrorCode::NotFound, e))?;
+    let target = config.command.target();
+    let mut workspaces = match &profile.database_file {
+        Some(database_file) => open_database(database_file, target)?,
+        None => load(FileStore::new(&profile.workspaces_file), target)?,
+    };
+    workspaces.set_remaps(
+        file_config.remaps_for(&gethostname::gethostname().to_string_lossy()),
+    );
+    workspaces.include(&file_config.includes)?;
+    workspaces.journal = Some(Journal::new(file_config.journal_file()));
+
+    // A broken .workspace.toml shouldn't prevent working with the registry
+    if let Ok(current_dir) = std::env::current_dir() {
+        if let Err(e) = workspaces.discover_local(&current_dir) {
+            output::warn(e);
+        }
+    }
+
+    // Conflicting included definitions only get in the way of the workspace they name
+    if let Some(error) = config
+        .command
+        .target()
+        .and_then(|name| workspaces.conflict(name))
+    {
+        return Err(error);
+    }
 
     // Initialize command
-    return Command::run(workspaces, config.command.clone()).into();
+    Command::run(workspaces, &file_config, config.command.clone()).into()
+}
+
+/// Loads the registry, or only the workspace called `target` for commands acting on one
+fn load<S: WorkspaceStore + 'static>(
+    store: S,
+    target: Option<&str>,
+) -> Result<Workspaces, WorkspaceError> {
+    match target {
+        Some(name) => Workspaces::with_named(store, name),
+        None => Workspaces::with_store(store),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_database(
+    database_file: &std::path::Path,
+    target: Option<&str>,
+) -> Result<Workspaces, WorkspaceError> {
+    load(SqliteStore::open(database_file)?, target)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_database(_: &std::path::Path, _: Option<&str>) -> Result<Workspaces, WorkspaceError> {
+    Err(WorkspaceError::new(
+        "database_file is set in the configuration, but ws was built without the sqlite feature"
+            .to_string(),
+        Severity::Error,
+    ))
 }
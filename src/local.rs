@@ -0,0 +1,77 @@
+use crate::{is_valid_tag, paths, Shell, Workspace, WorkspaceError, WorkspaceSource};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of a project-local workspace definition, meant to be committed with the project
+pub const LOCAL_FILE_NAME: &str = ".workspace.toml";
+
+/// A `.workspace.toml` file. Only the name is required: the path defaults to the directory
+/// holding the file and the shell to the platform default.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LocalDefinition {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub init_commands: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl LocalDefinition {
+    /// Builds the workspace defined by the file at `file_path`, resolving a relative path
+    /// against the directory that contains the file. `~` and environment variables are kept
+    /// unexpanded, like in the registry.
+    pub fn into_workspace(self, file_path: &Path) -> Workspace {
+        let dir = file_path.parent().unwrap_or(Path::new("."));
+        let path = match self.path {
+            Some(path) => paths::normalize(&path, dir),
+            None => dir.to_path_buf(),
+        };
+
+        let mut workspace = Workspace::new(
+            self.name,
+            path,
+            self.shell
+                .unwrap_or_else(|| Shell::default_name().to_string()),
+            self.init_commands,
+        );
+        workspace.env = self.env;
+        workspace.tags = self.tags;
+        workspace.source = WorkspaceSource::Local(file_path.to_path_buf());
+        workspace
+    }
+}
+
+/// Walks up from `start` and returns the path of the first `.workspace.toml` found
+pub fn find_local_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(LOCAL_FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+/// Loads the workspace defined by the nearest `.workspace.toml` above `start`, if any
+pub fn discover(start: &Path) -> Result<Option<Workspace>, WorkspaceError> {
+    let file_path = match find_local_file(start) {
+        Some(file_path) => file_path,
+        None => return Ok(None),
+    };
+
+    let contents = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Could not read {}: {}", file_path.display(), e))?;
+    let definition: LocalDefinition = toml::from_str(&contents)
+        .map_err(|e| format!("Could not parse {}: {}", file_path.display(), e))?;
+    if let Some(tag) = definition.tags.iter().find(|tag| !is_valid_tag(tag)) {
+        return Err(format!("Invalid tag {:?} in {}", tag, file_path.display()).into());
+    }
+
+    Ok(Some(definition.into_workspace(&file_path)))
+}
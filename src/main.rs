@@ -1,13 +1,35 @@
 use std::process;
-use workspace_mgr::{configuration::Config, errors::Severity};
+use workspace_mgr::{configuration::Config, errors::{ErrorCode, Severity, WorkspaceError}, output};
 use clap::Parser;
 
 fn main() {
     // Get the command line arguments
-    let config = Config::parse();
+    let config = match Config::try_parse() {
+        Ok(config) => config,
+        Err(e) => {
+            // Tools asking for JSON get invalid arguments reported as JSON too
+            if e.use_stderr() && std::env::args().any(|arg| arg == "--json") {
+                output::set_json(true);
+                let message = e.to_string().trim_end().to_string();
+                let error = WorkspaceError::coded(ErrorCode::InvalidArgument, message);
+                println!("{}", output::document(None, &Err(error)));
+                process::exit(2);
+            }
+            e.exit()
+        }
+    };
+    let json = config.json;
+    let command = config.command.to_string();
+    output::set_json(json);
 
     // Run the application
-    if let Err(e) = workspace_mgr::run(config) {
+    let result = workspace_mgr::run(config);
+    if json {
+        println!("{}", output::document(Some(&command), &result));
+        if result.is_err() {
+            process::exit(1);
+        }
+    } else if let Err(e) = result {
         if e.severity == Severity::Error {
             eprintln!("Error: {e}");
             process::exit(1);
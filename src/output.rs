@@ -0,0 +1,104 @@
+use crate::{ErrorCode, WorkspaceError};
+use serde::Serialize;
+use serde_json::Value;
+use std::{cell::RefCell, fmt::Display};
+
+/// What a command printed, collected instead when `--json` is given so it can be emitted as a
+/// single document
+#[derive(Debug, Default)]
+struct Report {
+    json: bool,
+    messages: Vec<String>,
+    warnings: Vec<String>,
+    data: Option<Value>,
+}
+
+thread_local! {
+    static REPORT: RefCell<Report> = RefCell::new(Report::default());
+}
+
+/// Switches between printing human-readable text and collecting a JSON document
+pub fn set_json(json: bool) {
+    REPORT.with(|report| report.borrow_mut().json = json);
+}
+
+pub fn is_json() -> bool {
+    REPORT.with(|report| report.borrow().json)
+}
+
+/// Prints a line for the user, or adds it to the messages of the JSON document
+pub fn message(message: String) {
+    REPORT.with(|report| {
+        let mut report = report.borrow_mut();
+        if report.json {
+            report.messages.push(message);
+        } else {
+            println!("{}", message);
+        }
+    })
+}
+
+/// Prints a warning to stderr, or adds it to the warnings of the JSON document
+pub fn warn(warning: impl Display) {
+    REPORT.with(|report| {
+        let mut report = report.borrow_mut();
+        if report.json {
+            report.warnings.push(warning.to_string());
+        } else {
+            eprintln!("Warning: {}", warning);
+        }
+    })
+}
+
+/// Sets the result of the command in the JSON document, ignored without `--json`
+pub fn data<T: Serialize>(value: &T) {
+    REPORT.with(|report| {
+        let mut report = report.borrow_mut();
+        if report.json {
+            report.data = Some(serde_json::to_value(value).unwrap_or(Value::Null));
+        }
+    })
+}
+
+/// The error to return instead of prompting when the user can't be asked
+pub fn confirmation_required(flag: &str) -> WorkspaceError {
+    WorkspaceError::coded(
+        ErrorCode::ConfirmationRequired,
+        format!("Confirmation is required, pass {} to go ahead", flag),
+    )
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    /// Missing when the arguments couldn't be parsed
+    command: Option<&'a str>,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a WorkspaceError>,
+    data: Value,
+    messages: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Builds the JSON document for `command` from everything collected so far and starts over
+pub fn document(command: Option<&str>, result: &Result<(), WorkspaceError>) -> String {
+    let report = REPORT.with(|report| std::mem::take(&mut *report.borrow_mut()));
+    let document = Document {
+        command,
+        ok: result.is_ok(),
+        error: result.as_ref().err(),
+        data: report.data.unwrap_or(Value::Null),
+        messages: report.messages,
+        warnings: report.warnings,
+    };
+    // Only strings and numbers, which always serialize
+    serde_json::to_string_pretty(&document).expect("the document serializes")
+}
+
+/// `println!` that goes through `message`
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::output::message(format!($($arg)*))
+    };
+}
+pub(crate) use say;
@@ -0,0 +1,111 @@
+use crate::{ErrorCode, Workspace, WorkspaceError};
+use std::{ffi::OsString, path::PathBuf};
+
+/// Field-level changes to a workspace, as given to `ws modify`. Command positions start at 1
+/// and refer to the list as it is after the previous kind of change, applied in field order.
+#[derive(Debug, Clone, Default)]
+pub struct Patch {
+    pub path: Option<PathBuf>,
+    pub shell: Option<String>,
+    /// Replaces all init commands
+    pub init_commands: Option<Vec<OsString>>,
+    /// Positions of commands to remove, all referring to the list before any is removed
+    pub remove_commands: Vec<usize>,
+    /// Commands to insert at a position
+    pub insert_commands: Vec<(usize, OsString)>,
+    /// Commands to move from one position to another
+    pub move_commands: Vec<(usize, usize)>,
+    /// Commands to append
+    pub add_commands: Vec<OsString>,
+    /// Environment variables to set
+    pub set_env: Vec<(String, String)>,
+    /// Environment variables to remove
+    pub unset_env: Vec<String>,
+    /// Replaces the tags
+    pub tags: Option<Vec<String>>,
+}
+
+impl Patch {
+    /// Whether the patch changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.path.is_none()
+            && self.shell.is_none()
+            && self.init_commands.is_none()
+            && self.remove_commands.is_empty()
+            && self.insert_commands.is_empty()
+            && self.move_commands.is_empty()
+            && self.add_commands.is_empty()
+            && self.set_env.is_empty()
+            && self.unset_env.is_empty()
+            && self.tags.is_none()
+    }
+
+    /// Applies the changes to `workspace`, leaving it untouched if a position is out of range
+    pub fn apply(&self, workspace: &mut Workspace) -> Result<(), WorkspaceError> {
+        let mut patched = workspace.clone();
+        if let Some(path) = &self.path {
+            patched.path = path.clone();
+        }
+        if let Some(shell) = &self.shell {
+            patched.shell = shell.clone();
+        }
+        if let Some(init_commands) = &self.init_commands {
+            patched.init_commands = init_commands.clone();
+        }
+
+        let commands = &mut patched.init_commands;
+        let mut removed = self.remove_commands.clone();
+        removed.sort_unstable();
+        removed.dedup();
+        // Back to front, so the positions still refer to the original list
+        for &position in removed.iter().rev() {
+            let index = index_of(position, commands.len())?;
+            commands.remove(index);
+        }
+        for (position, command) in &self.insert_commands {
+            // Inserting right after the last command is allowed
+            let index = index_of(*position, commands.len() + 1)?;
+            commands.insert(index, command.clone());
+        }
+        for &(from, to) in &self.move_commands {
+            let from = index_of(from, commands.len())?;
+            let to = index_of(to, commands.len())?;
+            let command = commands.remove(from);
+            commands.insert(to, command);
+        }
+        commands.extend(self.add_commands.iter().cloned());
+
+        for (name, value) in &self.set_env {
+            patched.env.insert(name.clone(), value.clone());
+        }
+        for name in &self.unset_env {
+            patched.env.remove(name);
+        }
+        if let Some(tags) = &self.tags {
+            patched.tags = tags.clone();
+        }
+
+        *workspace = patched;
+        Ok(())
+    }
+}
+
+/// The index of the 1-based `position` in a list of `len` entries
+fn index_of(position: usize, len: usize) -> Result<usize, WorkspaceError> {
+    if len == 0 {
+        return Err(WorkspaceError::coded(
+            ErrorCode::InvalidArgument,
+            "The workspace has no init commands".to_string(),
+        ));
+    }
+    if position == 0 || position > len {
+        return Err(WorkspaceError::coded(
+            ErrorCode::InvalidArgument,
+            format!(
+                "There is no init command at position {}, positions go from 1 to {}",
+                position, len
+            ),
+        ));
+    }
+    Ok(position - 1)
+}
@@ -0,0 +1,296 @@
+use directories::BaseDirs;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    path::{Component, Path, PathBuf},
+};
+
+/// Whether `path` starts with `~` or refers to an environment variable, in which case it is
+/// stored as typed and only expanded when used
+pub fn has_placeholders(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|text| is_home(text).is_some() || text.contains('$'))
+}
+
+/// Makes a path typed by the user independent of the directory it was typed in: relative paths
+/// are resolved against `base` and cleaned up, while paths with placeholders are kept as they are
+/// so the registry stays portable across machines
+pub fn normalize(path: &Path, base: &Path) -> PathBuf {
+    if has_placeholders(path) {
+        return path.to_path_buf();
+    }
+    if path.is_absolute() {
+        return clean(path);
+    }
+
+    let joined = base.join(path);
+    joined.canonicalize().unwrap_or_else(|_| clean(&joined))
+}
+
+/// Expands a leading `~` to the home directory and `$VAR` or `${VAR}` to the value of the
+/// environment variable
+pub fn expand(path: &Path) -> Result<PathBuf, String> {
+    // Placeholders are ASCII, so a path that isn't valid UTF-8 has none
+    let text = match path.to_str() {
+        Some(text) => text,
+        None => return Ok(path.to_path_buf()),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = text;
+    if let Some(after) = is_home(text) {
+        let home = BaseDirs::new().ok_or("Could not find the home directory")?;
+        expanded.push_str(&home.home_dir().to_string_lossy());
+        rest = after;
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let (name, remaining) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => return Err(format!("Unterminated ${{ in {}", text)),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        if name.is_empty() {
+            // A lone `$` is part of the path
+            expanded.push('$');
+        } else {
+            let value =
+                env::var(name).map_err(|_| format!("Environment variable {} is not set", name))?;
+            expanded.push_str(&value);
+        }
+        rest = remaining;
+    }
+    expanded.push_str(rest);
+
+    Ok(PathBuf::from(expanded))
+}
+
+/// The rest of `text` after a leading `~` that stands for the home directory
+fn is_home(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix('~')?;
+    match rest.chars().next() {
+        None => Some(rest),
+        Some(c) if std::path::is_separator(c) => Some(rest),
+        // `~user` isn't supported
+        Some(_) => None,
+    }
+}
+
+/// Removes `.` components and resolves `..` lexically
+fn clean(path: &Path) -> PathBuf {
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !cleaned.pop() {
+                    cleaned.push(component);
+                }
+            }
+            _ => cleaned.push(component),
+        }
+    }
+    cleaned
+}
+
+/// Replaces the path prefix `from` with `to`, for machines that keep projects under a
+/// different root
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remap {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Applies the remap with the longest matching prefix, if any
+pub fn remap(path: &Path, remaps: &[Remap]) -> PathBuf {
+    remaps
+        .iter()
+        .filter_map(|remap| {
+            path.strip_prefix(&remap.from)
+                .ok()
+                .map(|rest| (remap, rest))
+        })
+        .max_by_key(|(remap, _)| remap.from.components().count())
+        .map_or_else(
+            || path.to_path_buf(),
+            // Joining an empty path would add a trailing separator
+            |(remap, rest)| {
+                if rest.as_os_str().is_empty() {
+                    remap.to.clone()
+                } else {
+                    remap.to.join(rest)
+                }
+            },
+        )
+}
+
+/// How paths and other OS strings are stored: as a plain string when they are valid UTF-8, which
+/// they almost always are, and as their raw code units otherwise so they round-trip losslessly
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EncodedOsStr {
+    Utf8(String),
+    /// The bytes of a Unix path
+    Unix {
+        unix: Vec<u8>,
+    },
+    /// The UTF-16 code units of a Windows path
+    Windows {
+        windows: Vec<u16>,
+    },
+}
+
+impl EncodedOsStr {
+    fn encode(value: &OsStr) -> EncodedOsStr {
+        match value.to_str() {
+            Some(text) => EncodedOsStr::Utf8(text.to_string()),
+            None => encode_raw(value),
+        }
+    }
+
+    fn decode(self) -> Result<OsString, String> {
+        match self {
+            EncodedOsStr::Utf8(text) => Ok(text.into()),
+            EncodedOsStr::Unix { unix } => decode_unix(unix),
+            EncodedOsStr::Windows { windows } => decode_windows(windows),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn encode_raw(value: &OsStr) -> EncodedOsStr {
+    use std::os::unix::ffi::OsStrExt;
+    EncodedOsStr::Unix {
+        unix: value.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(windows)]
+fn encode_raw(value: &OsStr) -> EncodedOsStr {
+    use std::os::windows::ffi::OsStrExt;
+    EncodedOsStr::Windows {
+        windows: value.encode_wide().collect(),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn encode_raw(value: &OsStr) -> EncodedOsStr {
+    EncodedOsStr::Utf8(value.to_string_lossy().into_owned())
+}
+
+#[cfg(unix)]
+fn decode_unix(bytes: Vec<u8>) -> Result<OsString, String> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn decode_unix(_: Vec<u8>) -> Result<OsString, String> {
+    Err("a path that isn't valid UTF-8 was written on Unix and can't be used here".to_string())
+}
+
+#[cfg(windows)]
+fn decode_windows(units: Vec<u16>) -> Result<OsString, String> {
+    use std::os::windows::ffi::OsStringExt;
+    Ok(OsString::from_wide(&units))
+}
+
+#[cfg(not(windows))]
+fn decode_windows(_: Vec<u16>) -> Result<OsString, String> {
+    Err("a path that isn't valid UTF-8 was written on Windows and can't be used here".to_string())
+}
+
+/// `#[serde(with)]` helpers for a path or OS string, see `EncodedOsStr`
+pub mod os_string {
+    use super::EncodedOsStr;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use std::ffi::{OsStr, OsString};
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<OsStr>,
+        S: Serializer,
+    {
+        EncodedOsStr::encode(value.as_ref()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<OsString>,
+        D: Deserializer<'de>,
+    {
+        EncodedOsStr::deserialize(deserializer)?
+            .decode()
+            .map(T::from)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// `#[serde(with)]` helpers for an optional path or OS string
+pub mod option_os_string {
+    use super::EncodedOsStr;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use std::ffi::{OsStr, OsString};
+
+    pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<OsStr>,
+        S: Serializer,
+    {
+        value
+            .as_ref()
+            .map(|value| EncodedOsStr::encode(value.as_ref()))
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: From<OsString>,
+        D: Deserializer<'de>,
+    {
+        Option::<EncodedOsStr>::deserialize(deserializer)?
+            .map(|value| value.decode().map(T::from).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// `#[serde(with)]` helpers for a list of paths or OS strings
+pub mod os_strings {
+    use super::EncodedOsStr;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::ffi::{OsStr, OsString};
+
+    pub fn serialize<T, S>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<OsStr>,
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            values
+                .iter()
+                .map(|value| EncodedOsStr::encode(value.as_ref())),
+        )
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        T: From<OsString>,
+        D: Deserializer<'de>,
+    {
+        Vec::<EncodedOsStr>::deserialize(deserializer)?
+            .into_iter()
+            .map(|value| value.decode().map(T::from).map_err(D::Error::custom))
+            .collect()
+    }
+}
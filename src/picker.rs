@@ -0,0 +1,244 @@
+use crate::{jump, Workspace, WorkspaceError};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+use std::io::{self, BufRead, IsTerminal, Stdout, Write};
+
+/// Lines at the bottom of the screen used by the preview pane
+const PREVIEW_HEIGHT: u16 = 6;
+
+/// Lets the user choose one of `workspaces`, with a full-screen picker on a terminal and a
+/// numbered prompt otherwise. Returns `None` if the user cancels.
+pub fn pick(workspaces: &[Workspace]) -> Result<Option<Workspace>, WorkspaceError> {
+    if workspaces.is_empty() {
+        return Err("No workspaces".to_string().into());
+    }
+
+    if io::stdin().is_terminal() && io::stdout().is_terminal() {
+        pick_interactive(workspaces).map_err(|e| format!("Could not run the picker: {}", e).into())
+    } else {
+        pick_numbered(workspaces, &mut io::stdin().lock())
+    }
+}
+
+/// The workspaces matching every whitespace-separated term of `query`, best first
+pub fn filter<'a>(query: &str, workspaces: &'a [Workspace]) -> Vec<&'a Workspace> {
+    let terms: Vec<String> = query.split_whitespace().map(|t| t.to_string()).collect();
+    let mut matches: Vec<(f64, &Workspace)> = workspaces
+        .iter()
+        .filter_map(|w| jump::match_score(&terms, w).map(|score| (score, w)))
+        .collect();
+
+    // A stable sort keeps the registry order for an empty query
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+    matches.into_iter().map(|(_, w)| w).collect()
+}
+
+/// Lists the workspaces and reads the number of the chosen one from `input`
+pub fn pick_numbered(
+    workspaces: &[Workspace],
+    input: &mut dyn BufRead,
+) -> Result<Option<Workspace>, WorkspaceError> {
+    for (index, workspace) in workspaces.iter().enumerate() {
+        println!(
+            "{}. {} ({})",
+            index + 1,
+            workspace.name,
+            workspace.path.display()
+        );
+    }
+    print!("Workspace number (empty to cancel): ");
+    io::stdout().flush().map_err(|e| e.to_string())?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer).map_err(|e| e.to_string())?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(None);
+    }
+
+    match answer.parse::<usize>() {
+        Ok(number) if (1..=workspaces.len()).contains(&number) => {
+            Ok(Some(workspaces[number - 1].clone()))
+        }
+        _ => Err(format!("{} is not a workspace number", answer).into()),
+    }
+}
+
+/// Restores the terminal when the picker is done, even if drawing failed
+struct RawScreen {
+    stdout: Stdout,
+}
+
+impl RawScreen {
+    fn enter() -> io::Result<RawScreen> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(RawScreen { stdout })
+    }
+}
+
+impl Drop for RawScreen {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn pick_interactive(workspaces: &[Workspace]) -> io::Result<Option<Workspace>> {
+    let mut screen = RawScreen::enter()?;
+    let mut query = String::new();
+    let mut selected = 0;
+
+    loop {
+        let matches = filter(&query, workspaces);
+        selected = selected.min(matches.len().saturating_sub(1));
+        draw(
+            &mut screen.stdout,
+            &query,
+            &matches,
+            workspaces.len(),
+            selected,
+        )?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => key,
+            _ => continue,
+        };
+        match key {
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } => return Ok(None),
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => return Ok(None),
+            KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            } => return Ok(matches.get(selected).map(|w| (*w).clone())),
+            KeyEvent {
+                code: KeyCode::Up, ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('p'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => selected = selected.saturating_sub(1),
+            KeyEvent {
+                code: KeyCode::Down,
+                ..
+            }
+            | KeyEvent {
+                code: KeyCode::Char('n'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => selected += 1,
+            KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            } => {
+                query.pop();
+                selected = 0;
+            }
+            KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers,
+                ..
+            } if !modifiers.contains(KeyModifiers::CONTROL) => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw(
+    stdout: &mut Stdout,
+    query: &str,
+    matches: &[&Workspace],
+    total: usize,
+    selected: usize,
+) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let width = width as usize;
+    let list_height = height.saturating_sub(PREVIEW_HEIGHT + 2) as usize;
+
+    queue!(
+        stdout,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        Print(truncate(
+            &format!("> {}  [{}/{}]", query, matches.len(), total),
+            width
+        ))
+    )?;
+
+    // Scroll so the selection stays visible
+    let first = (selected + 1).saturating_sub(list_height);
+    for (row, workspace) in matches.iter().skip(first).take(list_height).enumerate() {
+        queue!(stdout, cursor::MoveTo(0, row as u16 + 1))?;
+        let line = truncate(
+            &format!("  {}  {}", workspace.name, workspace.path.display()),
+            width,
+        );
+        if first + row == selected {
+            queue!(
+                stdout,
+                SetAttribute(Attribute::Reverse),
+                Print(line),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(stdout, Print(line))?;
+        }
+    }
+
+    let preview_top = height.saturating_sub(PREVIEW_HEIGHT);
+    queue!(
+        stdout,
+        cursor::MoveTo(0, preview_top.saturating_sub(1)),
+        Print("-".repeat(width))
+    )?;
+    if let Some(workspace) = matches.get(selected) {
+        let commands: Vec<String> = workspace
+            .init_commands
+            .iter()
+            .map(|c| c.to_string_lossy().to_string())
+            .collect();
+        let lines = [
+            format!("Name:  {}", workspace.name),
+            format!("Path:  {}", workspace.path.display()),
+            format!("Shell: {}", workspace.shell),
+            format!("Runs:  {}", commands.join("; ")),
+            format!("Tags:  {}", workspace.tags.join(", ")),
+        ];
+        for (row, line) in lines.iter().enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, preview_top + row as u16),
+                Print(truncate(line, width))
+            )?;
+        }
+    }
+    queue!(
+        stdout,
+        cursor::MoveTo(0, height.saturating_sub(1)),
+        Print(truncate(
+            "Type to filter, Up/Down to select, Enter to launch, Esc to cancel",
+            width
+        ))
+    )?;
+    stdout.flush()
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
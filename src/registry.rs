@@ -0,0 +1,325 @@
+use crate::{output, Workspace};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::RandomState,
+    ffi::OsString,
+    fs::{self, File},
+    hash::{BuildHasher, Hasher},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the registry format written by this build. Version 2 gave every workspace an ID.
+pub const REGISTRY_VERSION: u32 = 2;
+
+/// The on-disk list of workspaces, stored as TOML
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Registry {
+    pub version: u32,
+    #[serde(default)]
+    pub workspaces: Vec<Workspace>,
+    /// Deleted workspaces, kept until they are purged
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash: Vec<TrashedWorkspace>,
+}
+
+/// A deleted workspace that can still be restored
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TrashedWorkspace {
+    /// Seconds since the Unix epoch
+    pub deleted_at: u64,
+    pub workspace: Workspace,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry {
+            version: REGISTRY_VERSION,
+            workspaces: Vec::new(),
+            trash: Vec::new(),
+        }
+    }
+}
+
+/// An advisory lock on a registry, held until dropped.
+///
+/// The lock is taken on a `.lock` file next to the registry rather than on the registry itself,
+/// because atomic saves replace the registry file and with it any lock held on the old one.
+pub struct RegistryLock {
+    _file: File,
+}
+
+impl RegistryLock {
+    pub fn acquire(file_path: &Path) -> Result<RegistryLock, String> {
+        let lock_path = sibling_path(file_path, ".lock");
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| format!("Could not open {}: {}", lock_path.display(), e))?;
+        file.lock()
+            .map_err(|e| format!("Could not lock {}: {}", lock_path.display(), e))?;
+
+        Ok(RegistryLock { _file: file })
+    }
+}
+
+impl Registry {
+    /// Reads the registry at `file_path` under its lock
+    pub fn load(file_path: &Path) -> Result<Registry, String> {
+        let _lock = RegistryLock::acquire(file_path)?;
+        Registry::read(file_path)
+    }
+
+    /// Runs a read-modify-write cycle on the registry at `file_path` while holding its lock, so
+    /// concurrent invocations never lose each other's changes
+    pub fn update<F, E>(file_path: &Path, modify: F) -> Result<Registry, E>
+    where
+        F: FnOnce(&mut Registry) -> Result<(), E>,
+        E: From<String>,
+    {
+        let _lock = RegistryLock::acquire(file_path)?;
+        let mut registry = Registry::read(file_path)?;
+        modify(&mut registry)?;
+        registry.save(file_path)?;
+        Ok(registry)
+    }
+
+    /// Reads the registry at `file_path`, migrating a legacy `name;path;shell;cmd` file in
+    /// place. The legacy contents are kept next to it with a `.bak` suffix.
+    ///
+    /// The caller is expected to hold the registry lock.
+    fn read(file_path: &Path) -> Result<Registry, String> {
+        let contents = match fs::read_to_string(file_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Registry::default()),
+            Err(e) => return Err(format!("Could not read {}: {}", file_path.display(), e)),
+        };
+
+        if contents.trim().is_empty() {
+            return Ok(Registry::default());
+        }
+
+        if !is_legacy(&contents) {
+            let mut registry = Registry::from_toml(&contents).map_err(|e| {
+                format!(
+                    "Could not parse {}, run `ws fsck` to check it: {}",
+                    file_path.display(),
+                    e
+                )
+            })?;
+            if registry.upgrade() {
+                registry.save(file_path)?;
+            }
+            return Ok(registry);
+        }
+
+        let registry = Registry::from_legacy(&contents).map_err(|e| {
+            format!(
+                "Could not migrate {}, run `ws fsck` to repair it: {}",
+                file_path.display(),
+                e
+            )
+        })?;
+
+        let backup = backup_path(file_path);
+        fs::copy(file_path, &backup)
+            .map_err(|e| format!("Could not back up {}: {}", file_path.display(), e))?;
+        registry.save(file_path)?;
+
+        output::warn(format!(
+            "Migrated {} to the TOML registry format (backup at {})",
+            file_path.display(),
+            backup.display()
+        ));
+        Ok(registry)
+    }
+
+    /// Replaces the registry at `file_path` atomically: the contents are written to a temporary
+    /// file which is then renamed over the registry, so a crash never leaves it truncated.
+    ///
+    /// The caller is expected to hold the registry lock.
+    pub fn save(&self, file_path: &Path) -> Result<(), String> {
+        write_atomically(file_path, &self.to_toml()?)
+    }
+
+    /// Parses a registry in either format without touching the file it came from
+    pub fn parse(contents: &str) -> Result<Registry, String> {
+        if contents.trim().is_empty() {
+            Ok(Registry::default())
+        } else if is_legacy(contents) {
+            Registry::from_legacy(contents)
+        } else {
+            Registry::from_toml(contents)
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Registry, String> {
+        let registry: Registry = toml::from_str(contents).map_err(|e| e.to_string())?;
+        if registry.version > REGISTRY_VERSION {
+            return Err(format!(
+                "registry version {} is newer than the supported version {}",
+                registry.version, REGISTRY_VERSION
+            ));
+        }
+        Ok(registry)
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| e.to_string())
+    }
+
+    /// Parses the legacy format, one `name;path;shell;cmd;cmd...` entry per line
+    pub fn from_legacy(contents: &str) -> Result<Registry, String> {
+        let mut workspaces = Vec::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut parts = line.split(';');
+            let (name, path, shell) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(path), Some(shell)) => (name, path, shell),
+                _ => {
+                    return Err(format!(
+                        "line {}: expected at least a name, a path and a shell",
+                        index + 1
+                    ))
+                }
+            };
+            let init_commands = parts
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect();
+
+            workspaces.push(Workspace::new(
+                name.to_string(),
+                path.into(),
+                shell.to_string(),
+                init_commands,
+            ));
+        }
+
+        let mut registry = Registry {
+            workspaces,
+            ..Registry::default()
+        };
+        registry.assign_ids();
+        Ok(registry)
+    }
+
+    /// Brings an older registry up to `REGISTRY_VERSION` and gives workspaces without a unique
+    /// ID a new one, returning whether anything changed
+    pub fn upgrade(&mut self) -> bool {
+        let outdated = self.version < REGISTRY_VERSION;
+        self.version = REGISTRY_VERSION;
+        self.assign_ids() || outdated
+    }
+
+    /// Gives every registered or trashed workspace that has no ID, or one already used by an
+    /// earlier entry, a new ID, returning whether any was assigned
+    pub fn assign_ids(&mut self) -> bool {
+        let mut taken: Vec<String> = self
+            .workspaces
+            .iter()
+            .chain(self.trash.iter().map(|trashed| &trashed.workspace))
+            .map(|w| w.id.clone())
+            .filter(|id| !id.is_empty())
+            .collect();
+
+        let mut seen: Vec<String> = Vec::new();
+        let mut assigned = false;
+        let trashed = self.trash.iter_mut().map(|trashed| &mut trashed.workspace);
+        for workspace in self.workspaces.iter_mut().chain(trashed) {
+            if workspace.id.is_empty() || seen.contains(&workspace.id) {
+                workspace.id = generate_id(&taken);
+                taken.push(workspace.id.clone());
+                assigned = true;
+            }
+            seen.push(workspace.id.clone());
+        }
+        assigned
+    }
+}
+
+/// A random 8 hex digit ID that isn't in `taken`
+pub(crate) fn generate_id(taken: &[String]) -> String {
+    loop {
+        // Every RandomState is seeded differently, which is all the randomness an ID needs
+        let mut hasher = RandomState::new().build_hasher();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        hasher.write_u128(nanos);
+
+        let id = format!("{:08x}", hasher.finish() as u32);
+        if !taken.contains(&id) {
+            return id;
+        }
+    }
+}
+
+/// Whether `contents` is a legacy registry: it isn't TOML, has `name;path;shell` entries and no
+/// line looks like a TOML key or table, which would make it a damaged registry instead. Legacy
+/// lines with fewer fields are still accepted so `ws fsck` can repair them.
+pub(crate) fn is_legacy(contents: &str) -> bool {
+    let lines: Vec<&str> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    contents.parse::<toml::Table>().is_err()
+        && lines.iter().any(|line| line.matches(';').count() >= 2)
+        && !lines.iter().any(|line| is_toml_line(line))
+}
+
+/// Whether `line` is a TOML `key = value` pair or `[table]` header
+fn is_toml_line(line: &str) -> bool {
+    let is_key = |key: &str| {
+        let key = key.trim();
+        !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-. \"'".contains(c))
+    };
+    let header = line.split('#').next().unwrap_or_default().trim_end();
+    let is_header =
+        header.starts_with('[') && header.ends_with(']') && is_key(header.trim_matches(['[', ']']));
+    is_header || line.split_once('=').is_some_and(|(key, _)| is_key(key))
+}
+
+/// Replaces the file at `file_path` with `contents` through a temporary file renamed over it,
+/// so a crash never leaves it truncated. The caller is expected to hold the file's lock.
+pub(crate) fn write_atomically(file_path: &Path, contents: &str) -> Result<(), String> {
+    let temp_path = sibling_path(file_path, ".tmp");
+    let write_error = |e: std::io::Error| format!("Could not write {}: {}", file_path.display(), e);
+
+    let mut file = File::create(&temp_path).map_err(write_error)?;
+    file.write_all(contents.as_bytes()).map_err(write_error)?;
+    file.sync_all().map_err(write_error)?;
+    drop(file);
+
+    fs::rename(&temp_path, file_path).map_err(write_error)
+}
+
+/// `file_path` with `suffix` appended to its file name
+fn sibling_path(file_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(file_path.as_os_str());
+    path.push(suffix);
+    path.into()
+}
+
+/// A free `.bak` path next to `file_path`
+pub(crate) fn backup_path(file_path: &Path) -> PathBuf {
+    let mut candidate = sibling_path(file_path, ".bak");
+
+    let mut n = 1;
+    while candidate.exists() {
+        candidate = sibling_path(file_path, &format!(".bak.{}", n));
+        n += 1;
+    }
+    candidate
+}
@@ -1,24 +1,105 @@
-use std::path::PathBuf;
+use crate::{ErrorCode, WorkspaceError};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Shells recognized by name, wherever they are installed
+const KNOWN_SHELLS: &[&str] = &[
+    "sh",
+    "bash",
+    "zsh",
+    "fish",
+    "dash",
+    "ksh",
+    "mksh",
+    "csh",
+    "tcsh",
+    "nu",
+    "xonsh",
+    "elvish",
+    "pwsh",
+    "powershell",
+    "cmd",
+];
 
 pub struct Shell {
-    pub working_dir: String,
+    pub working_dir: PathBuf,
     pub name: String,
+    /// Extra environment variables for the shell
+    pub env: BTreeMap<String, String>,
 }
 
 impl Shell {
-    pub fn new(wdir: &PathBuf, shell: &str) -> Self {
-        let working_dir = wdir.to_str().expect("Invalid path").to_string();
+    pub fn new(wdir: &Path, shell: &str) -> Self {
         Self {
-            working_dir,
+            working_dir: wdir.to_path_buf(),
             name: shell.to_string(),
+            env: BTreeMap::new(),
+        }
+    }
+
+    /// The shell used when a workspace doesn't name one
+    pub fn default_name() -> &'static str {
+        if cfg!(target_os = "windows") {
+            "cmd"
+        } else {
+            "sh"
+        }
+    }
+
+    /// The user's shell from `$SHELL`, or the default one if it isn't set
+    pub fn detect_name() -> String {
+        std::env::var_os("SHELL")
+            .and_then(|shell| {
+                Path::new(&shell)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| Self::default_name().to_string())
+    }
+
+    /// Where the executable `shell` is, either as given if it is a path or the first match on
+    /// `PATH`
+    pub fn find_executable(shell: &str) -> Option<PathBuf> {
+        let path = Path::new(shell);
+        if path.components().count() > 1 {
+            return path.is_file().then(|| path.to_path_buf());
+        }
+        let extensions: &[&str] = if cfg!(target_os = "windows") {
+            &["", ".exe", ".cmd", ".bat"]
+        } else {
+            &[""]
+        };
+        std::env::split_paths(&std::env::var_os("PATH")?).find_map(|dir| {
+            extensions
+                .iter()
+                .map(|extension| dir.join(format!("{}{}", shell, extension)))
+                .find(|candidate| candidate.is_file())
+        })
+    }
+
+    /// Whether `shell` names a shell rather than some other program: a well-known shell, or an
+    /// executable listed in `/etc/shells`
+    pub fn is_known(shell: &str) -> bool {
+        let name = Path::new(shell).file_stem().and_then(|name| name.to_str());
+        if name.is_some_and(|name| KNOWN_SHELLS.contains(&name)) {
+            return true;
         }
+        let listed = fs::read_to_string("/etc/shells").unwrap_or_default();
+        Self::find_executable(shell).is_some_and(|path| {
+            listed
+                .lines()
+                .any(|line| Path::new(line.trim()) == path.as_path())
+        })
     }
 
-    pub fn get_input(&self) {
-        self.run_command("".to_string());
+    pub fn get_input(&self) -> Result<(), WorkspaceError> {
+        self.run_command("".to_string())
     }
 
-    pub fn run_command(&self, command: String) {
+    pub fn run_command(&self, command: String) -> Result<(), WorkspaceError> {
         let mut cmd = std::process::Command::new(self.name.as_str());
         if self.name.as_str() == "pwsh.exe" {
             cmd.arg("-NoLogo");
@@ -29,12 +110,18 @@ impl Shell {
         match self.name.as_str() {
             "pwsh.exe" => {
                 cmd.arg("-wd");
-                cmd.arg(self.working_dir.as_str());
+                cmd.arg(&self.working_dir);
             }
             _ => {
-                cmd.current_dir(self.working_dir.as_str());
+                cmd.current_dir(&self.working_dir);
             }
         }
-        cmd.status().unwrap();
+        cmd.envs(&self.env);
+        cmd.status().map(|_| ()).map_err(|e| {
+            WorkspaceError::coded(
+                ErrorCode::Io,
+                format!("Could not run the shell {}: {}", self.name, e),
+            )
+        })
     }
 }
@@ -0,0 +1,450 @@
+use crate::{
+    Registry, Severity, TrashedWorkspace, Workspace, WorkspaceError, WorkspaceStore,
+    REGISTRY_VERSION,
+};
+use rusqlite::{params, Connection, OptionalExtension, ToSql, Transaction, TransactionBehavior};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS metadata (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS workspaces (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        path TEXT NOT NULL,
+        definition TEXT NOT NULL,
+        position INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS workspaces_name ON workspaces (name);
+    CREATE TABLE IF NOT EXISTS trash (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        deleted_at INTEGER NOT NULL,
+        definition TEXT NOT NULL,
+        position INTEGER NOT NULL DEFAULT 0
+    );
+";
+
+/// Stores the registry in a SQLite database.
+///
+/// `name` is an indexed column for lookups, while the full workspace, including its init
+/// commands, is kept as a TOML definition so new fields don't need a schema change. The trash is
+/// kept the same way in its own table. Rows are ordered by their `position` column, so a
+/// workspace restored by an undo goes back where it was.
+pub struct SqliteStore {
+    connection: Connection,
+    database_path: Option<PathBuf>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(database_path: P) -> Result<SqliteStore, WorkspaceError> {
+        let connection = Connection::open(database_path.as_ref()).map_err(sql_error)?;
+        SqliteStore::with_connection(connection, Some(database_path.as_ref().to_path_buf()))
+    }
+
+    pub fn open_in_memory() -> Result<SqliteStore, WorkspaceError> {
+        SqliteStore::with_connection(Connection::open_in_memory().map_err(sql_error)?, None)
+    }
+
+    fn with_connection(
+        connection: Connection,
+        database_path: Option<PathBuf>,
+    ) -> Result<SqliteStore, WorkspaceError> {
+        // Wait for other `ws` invocations instead of failing on a locked database
+        connection
+            .busy_timeout(Duration::from_secs(5))
+            .map_err(sql_error)?;
+        connection.execute_batch(SCHEMA).map_err(sql_error)?;
+        // Databases created before rows had positions keep their insertion order
+        for table in ["workspaces", "trash"] {
+            let query = format!("SELECT position FROM {} LIMIT 0", table);
+            if connection.prepare(&query).is_err() {
+                connection
+                    .execute_batch(&format!(
+                        "ALTER TABLE {0} ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+                         UPDATE {0} SET position = id;",
+                        table
+                    ))
+                    .map_err(sql_error)?;
+            }
+        }
+        connection
+            .execute(
+                "INSERT OR IGNORE INTO metadata (key, value) VALUES ('version', ?1)",
+                params![REGISTRY_VERSION.to_string()],
+            )
+            .map_err(sql_error)?;
+
+        let store = SqliteStore {
+            connection,
+            database_path,
+        };
+        if store.version()? < REGISTRY_VERSION {
+            store.update(&mut |registry| {
+                registry.upgrade();
+                Ok(())
+            })?;
+        }
+        Ok(store)
+    }
+
+    fn read(&self) -> Result<Registry, WorkspaceError> {
+        Ok(Registry {
+            version: self.version()?,
+            workspaces: self.workspaces()?.into_iter().map(|(_, _, w)| w).collect(),
+            trash: self.trash()?.into_iter().map(|(_, _, t)| t).collect(),
+        })
+    }
+
+    fn version(&self) -> Result<u32, WorkspaceError> {
+        let version: String = self
+            .connection
+            .query_row(
+                "SELECT value FROM metadata WHERE key = 'version'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(sql_error)?;
+        version.parse().map_err(|_| {
+            format!(
+                "Invalid registry version {:?} in {}",
+                version,
+                self.location()
+            )
+            .into()
+        })
+    }
+
+    fn workspaces(&self) -> Result<Vec<(i64, String, Workspace)>, WorkspaceError> {
+        self.rows(
+            "SELECT id, definition FROM workspaces ORDER BY position, id",
+            params![],
+        )
+    }
+
+    fn trash(&self) -> Result<Vec<(i64, String, TrashedWorkspace)>, WorkspaceError> {
+        self.rows(
+            "SELECT id, definition FROM trash ORDER BY position, id",
+            params![],
+        )
+    }
+
+    /// Runs a query selecting `id, definition` and parses each definition
+    fn rows<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<(i64, String, T)>, WorkspaceError> {
+        let mut statement = self.connection.prepare(query).map_err(sql_error)?;
+        let rows = statement
+            .query_map(params, |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sql_error)?;
+
+        let mut parsed = Vec::new();
+        for row in rows {
+            let (id, definition) = row.map_err(sql_error)?;
+            let value = toml::from_str(&definition)
+                .map_err(|e| format!("Invalid row {} in {}: {}", id, self.location(), e))?;
+            parsed.push((id, definition, value));
+        }
+        Ok(parsed)
+    }
+}
+
+impl WorkspaceStore for SqliteStore {
+    fn load(&self) -> Result<Registry, WorkspaceError> {
+        self.read()
+    }
+
+    /// Only the rows whose definition changed are written, so removing one workspace out of
+    /// hundreds is a single delete
+    fn update(
+        &self,
+        modify: &mut dyn FnMut(&mut Registry) -> Result<(), WorkspaceError>,
+    ) -> Result<Registry, WorkspaceError> {
+        // An immediate transaction takes the write lock up front, so concurrent updates are
+        // serialized instead of overwriting each other
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)
+                .map_err(sql_error)?;
+
+        let workspaces = self.workspaces()?;
+        let trash = self.trash()?;
+        let mut registry = Registry {
+            version: self.version()?,
+            workspaces: workspaces.iter().map(|(_, _, w)| w.clone()).collect(),
+            trash: trash.iter().map(|(_, _, t)| t.clone()).collect(),
+        };
+        modify(&mut registry)?;
+
+        sync(
+            &transaction,
+            "workspaces",
+            workspaces,
+            &registry.workspaces,
+            |workspace| workspace.id.clone(),
+            |row, workspace, definition, position| match row {
+                Some(id) => transaction.execute(
+                    "UPDATE workspaces SET name = ?1, path = ?2, definition = ?3, position = ?4
+                     WHERE id = ?5",
+                    params![
+                        workspace.name,
+                        workspace.path.to_string_lossy(),
+                        definition,
+                        position,
+                        id
+                    ],
+                ),
+                None => transaction.execute(
+                    "INSERT INTO workspaces (name, path, definition, position)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        workspace.name,
+                        workspace.path.to_string_lossy(),
+                        definition,
+                        position
+                    ],
+                ),
+            },
+        )?;
+        sync(
+            &transaction,
+            "trash",
+            trash,
+            &registry.trash,
+            |trashed| trashed.workspace.id.clone(),
+            |row, trashed, definition, position| match row {
+                Some(id) => transaction.execute(
+                    "UPDATE trash SET name = ?1, deleted_at = ?2, definition = ?3, position = ?4
+                     WHERE id = ?5",
+                    params![
+                        trashed.workspace.name,
+                        trashed.deleted_at,
+                        definition,
+                        position,
+                        id
+                    ],
+                ),
+                None => transaction.execute(
+                    "INSERT INTO trash (name, deleted_at, definition, position)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        trashed.workspace.name,
+                        trashed.deleted_at,
+                        definition,
+                        position
+                    ],
+                ),
+            },
+        )?;
+
+        transaction
+            .execute(
+                "UPDATE metadata SET value = ?1 WHERE key = 'version'",
+                params![registry.version.to_string()],
+            )
+            .map_err(sql_error)?;
+
+        transaction.commit().map_err(sql_error)?;
+        self.read()
+    }
+
+    fn location(&self) -> String {
+        match &self.database_path {
+            Some(path) => path.display().to_string(),
+            None => "memory (sqlite)".to_string(),
+        }
+    }
+
+    fn find_by_name(&self, name: &str) -> Result<Option<Workspace>, WorkspaceError> {
+        let definition: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT definition FROM workspaces WHERE name = ?1 ORDER BY position, id LIMIT 1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+
+        match definition {
+            Some(definition) => toml::from_str(&definition).map(Some).map_err(|e| {
+                format!("Invalid workspace {} in {}: {}", name, self.location(), e).into()
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn update_workspace(
+        &self,
+        name: &str,
+        id: &str,
+        modify: &mut dyn FnMut(&mut Workspace),
+    ) -> Result<Option<Workspace>, WorkspaceError> {
+        let transaction =
+            Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)
+                .map_err(sql_error)?;
+
+        let rows: Vec<(i64, String, Workspace)> = self.rows(
+            "SELECT id, definition FROM workspaces WHERE name = ?1",
+            params![name],
+        )?;
+        let (row, mut workspace) = match rows.into_iter().find(|(_, _, w)| w.id == id) {
+            Some((row, _, workspace)) => (row, workspace),
+            None => return Ok(None),
+        };
+        modify(&mut workspace);
+
+        let definition = toml::to_string(&workspace)
+            .map_err(|e| format!("Could not serialize workspace {}: {}", name, e))?;
+        transaction
+            .execute(
+                "UPDATE workspaces SET name = ?1, path = ?2, definition = ?3 WHERE id = ?4",
+                params![
+                    workspace.name,
+                    workspace.path.to_string_lossy(),
+                    definition,
+                    row
+                ],
+            )
+            .map_err(sql_error)?;
+        transaction.commit().map_err(sql_error)?;
+        Ok(Some(workspace))
+    }
+}
+
+/// Writes the `wanted` values to `table` in order, matching them to the `stored` rows by `key`
+/// so a changed value is updated in place. Values without a stored row are inserted between
+/// their neighbours and rows that are no longer wanted are deleted.
+fn sync<T, K, W>(
+    transaction: &Transaction,
+    table: &str,
+    stored: Vec<(i64, String, T)>,
+    wanted: &[T],
+    key: K,
+    write: W,
+) -> Result<(), WorkspaceError>
+where
+    T: Serialize,
+    K: Fn(&T) -> String,
+    W: Fn(Option<i64>, &T, String, i64) -> rusqlite::Result<usize>,
+{
+    let positions: HashMap<i64, i64> = transaction
+        .prepare(&format!("SELECT id, position FROM {}", table))
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .map_err(sql_error)?;
+    let mut stored: Vec<Option<(i64, String, String)>> = stored
+        .into_iter()
+        .map(|(id, definition, value)| Some((id, key(&value), definition)))
+        .collect();
+
+    let mut rows = Vec::with_capacity(wanted.len());
+    for value in wanted {
+        let definition = toml::to_string(value)
+            .map_err(|e| format!("Could not serialize a row of {}: {}", table, e))?;
+        let value_key = key(value);
+        // Values without a key can only be matched by their definition
+        let is_row = |(_, stored_key, stored_definition): &(i64, String, String)| {
+            if value_key.is_empty() {
+                *stored_definition == definition
+            } else {
+                *stored_key == value_key
+            }
+        };
+        let row = stored
+            .iter()
+            .position(|row| row.as_ref().is_some_and(is_row))
+            .and_then(|index| stored[index].take());
+        rows.push((row, definition));
+    }
+
+    let stored_positions: Vec<Option<i64>> = rows
+        .iter()
+        .map(|(row, _)| {
+            row.as_ref()
+                .and_then(|(id, _, _)| positions.get(id).copied())
+        })
+        .collect();
+    let new_positions = assign_positions(&stored_positions);
+    for (((row, definition), value), (position, stored_position)) in rows
+        .into_iter()
+        .zip(wanted)
+        .zip(new_positions.into_iter().zip(stored_positions))
+    {
+        match row {
+            Some((_, _, stored_definition))
+                if stored_definition == definition && stored_position == Some(position) => {}
+            Some((id, _, _)) => {
+                write(Some(id), value, definition, position).map_err(sql_error)?;
+            }
+            None => {
+                write(None, value, definition, position).map_err(sql_error)?;
+            }
+        }
+    }
+
+    for (id, _, _) in stored.into_iter().flatten() {
+        transaction
+            .execute(&format!("DELETE FROM {} WHERE id = ?1", table), params![id])
+            .map_err(sql_error)?;
+    }
+    Ok(())
+}
+
+/// Positions for rows in order, given the ones they are stored with. Stored positions are kept
+/// while they increase, the other rows get one right after the row before them, and everything
+/// is renumbered if there is no room left.
+fn assign_positions(stored: &[Option<i64>]) -> Vec<i64> {
+    let mut kept = Vec::with_capacity(stored.len());
+    let mut last = None;
+    for position in stored {
+        match (*position, last) {
+            (Some(position), Some(last)) if position <= last => kept.push(None),
+            (Some(position), _) => {
+                last = Some(position);
+                kept.push(Some(position));
+            }
+            (None, _) => kept.push(None),
+        }
+    }
+
+    let mut assigned = Vec::with_capacity(stored.len());
+    let mut previous: Option<i64> = None;
+    for (index, position) in kept.iter().enumerate() {
+        let position = match position {
+            Some(position) => *position,
+            None => {
+                let next = kept[index + 1..].iter().flatten().next().copied();
+                let position = match (previous, next) {
+                    (Some(previous), _) => previous + 1,
+                    (None, Some(next)) => next - 1,
+                    (None, None) => 0,
+                };
+                if next.is_some_and(|next| position >= next) {
+                    return (0..stored.len() as i64).collect();
+                }
+                position
+            }
+        };
+        previous = Some(position);
+        assigned.push(position);
+    }
+    assigned
+}
+
+fn sql_error(error: rusqlite::Error) -> WorkspaceError {
+    WorkspaceError::new(format!("Database error: {}", error), Severity::Error)
+}
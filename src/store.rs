@@ -0,0 +1,116 @@
+use crate::{ErrorCode, Registry, Workspace, WorkspaceError};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+/// A backend that persists the registry behind `Workspaces`
+pub trait WorkspaceStore {
+    /// Reads the current registry
+    fn load(&self) -> Result<Registry, WorkspaceError>;
+
+    /// Applies `modify` to the current registry and persists the result as a single update,
+    /// returning the registry as written
+    fn update(
+        &self,
+        modify: &mut dyn FnMut(&mut Registry) -> Result<(), WorkspaceError>,
+    ) -> Result<Registry, WorkspaceError>;
+
+    /// Describes where the registry lives, for messages
+    fn location(&self) -> String;
+
+    /// Finds a workspace by name. Backends with an index should override the full scan.
+    fn find_by_name(&self, name: &str) -> Result<Option<Workspace>, WorkspaceError> {
+        Ok(self.load()?.workspaces.into_iter().find(|w| w.name == name))
+    }
+
+    /// Applies `modify` to the registered workspace called `name` with the ID `id` as a single
+    /// update, returning it as written. Backends with an index should override the full update.
+    fn update_workspace(
+        &self,
+        name: &str,
+        id: &str,
+        modify: &mut dyn FnMut(&mut Workspace),
+    ) -> Result<Option<Workspace>, WorkspaceError> {
+        let mut updated = None;
+        self.update(&mut |registry| {
+            updated = registry
+                .workspaces
+                .iter_mut()
+                .find(|w| w.name == name && w.id == id)
+                .map(|stored| {
+                    modify(stored);
+                    stored.clone()
+                });
+            Ok(())
+        })?;
+        Ok(updated)
+    }
+}
+
+/// Stores the registry in a TOML file, see `Registry::load` and `Registry::update`
+pub struct FileStore {
+    pub file_path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: AsRef<Path>>(file_path: P) -> FileStore {
+        FileStore {
+            file_path: file_path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl WorkspaceStore for FileStore {
+    fn load(&self) -> Result<Registry, WorkspaceError> {
+        Registry::load(&self.file_path)
+            .map_err(|e| WorkspaceError::coded(ErrorCode::InvalidRegistry, e))
+    }
+
+    fn update(
+        &self,
+        modify: &mut dyn FnMut(&mut Registry) -> Result<(), WorkspaceError>,
+    ) -> Result<Registry, WorkspaceError> {
+        Registry::update(&self.file_path, modify)
+    }
+
+    fn location(&self) -> String {
+        self.file_path.display().to_string()
+    }
+}
+
+/// Keeps the registry in memory only, for tests and embedders that persist it themselves
+#[derive(Default)]
+pub struct MemoryStore {
+    registry: RefCell<Registry>,
+}
+
+impl MemoryStore {
+    pub fn new(mut registry: Registry) -> MemoryStore {
+        registry.upgrade();
+        MemoryStore {
+            registry: RefCell::new(registry),
+        }
+    }
+}
+
+impl WorkspaceStore for MemoryStore {
+    fn load(&self) -> Result<Registry, WorkspaceError> {
+        Ok(self.registry.borrow().clone())
+    }
+
+    fn update(
+        &self,
+        modify: &mut dyn FnMut(&mut Registry) -> Result<(), WorkspaceError>,
+    ) -> Result<Registry, WorkspaceError> {
+        // Work on a copy so a failed update leaves the store untouched
+        let mut registry = self.registry.borrow().clone();
+        modify(&mut registry)?;
+        *self.registry.borrow_mut() = registry.clone();
+        Ok(registry)
+    }
+
+    fn location(&self) -> String {
+        "memory".to_string()
+    }
+}
@@ -6,59 +6,1127 @@ pub fn clear(path: &str) {
 
 #[test]
 pub fn add() {
-    let file_config = match FileConfig::build() {
-        Ok(config) => config,
-        Err(e) => panic!("{}", e),
-    };
-
-    let file_path = file_config.test_workspaces_file;
-
-    let mut workspaces = Workspaces::new(&file_path.as_str());
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
     let workspace = Workspace::new(
         "test".to_string(),
         "test".to_string().into(),
         "cmd".to_string(),
         vec![],
     );
-    workspaces.add(workspace.clone());
+    workspaces.add(workspace.clone()).unwrap();
 
     assert_eq!(workspaces.workspaces[0], workspace);
-    clear(file_path.as_str());
+    assert_eq!(workspaces.store.load().unwrap().workspaces[0], workspace);
 }
 
 #[test]
 pub fn remove() {
-    let file_config = match FileConfig::build() {
-        Ok(config) => config,
-        Err(e) => panic!("{}", e),
-    };
-
-    let file_path = file_config.test_workspaces_file;
-
-    let mut workspaces = Workspaces::new(file_path.as_str());
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
     let workspace = Workspace::new(
         "test".to_string(),
         "test".to_string().into(),
         "cmd".to_string(),
         vec![],
     );
-    workspaces.add(workspace.clone());
-    workspaces.remove_from_file(&workspace);
+    workspaces.add(workspace).unwrap();
+    let workspace = workspaces.workspaces[0].clone();
+    workspaces.remove_from_file(&workspace).unwrap();
 
     assert_eq!(workspaces.workspaces.len(), 0);
-    clear(file_path.as_str());
+    assert_eq!(workspaces.store.load().unwrap().workspaces.len(), 0);
 }
 
 #[test]
 pub fn read_from_file() {
-    let file_config = match FileConfig::build() {
-        Ok(config) => config,
-        Err(e) => panic!("{}", e),
+    let file_path = std::env::temp_dir().join("ws_read_from_file.toml");
+    clear(file_path.to_str().unwrap());
+
+    let workspaces = Workspaces::new(file_path.to_str().unwrap()).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 0);
+    clear(file_path.to_str().unwrap());
+}
+
+#[test]
+pub fn failed_update_leaves_store_untouched() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    let workspace = Workspace::new(
+        "test".to_string(),
+        "test".to_string().into(),
+        "cmd".to_string(),
+        vec![],
+    );
+    workspaces.add(workspace.clone()).unwrap();
+
+    assert!(workspaces.add(workspace).is_err());
+    assert_eq!(workspaces.store.load().unwrap().workspaces.len(), 1);
+}
+
+#[test]
+pub fn migrate_legacy_file() {
+    let file_path = std::env::temp_dir().join("ws_migrate_legacy.txt");
+    let backup_path = std::env::temp_dir().join("ws_migrate_legacy.txt.bak");
+    clear(backup_path.to_str().unwrap());
+    std::fs::write(
+        &file_path,
+        "api;/srv/api;bash;cargo build;echo ready\nweb;/srv/web;sh;\n",
+    )
+    .unwrap();
+
+    let workspaces = Workspaces::new(file_path.to_str().unwrap()).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 2);
+    assert_eq!(
+        workspaces.workspaces[0].init_commands,
+        vec!["cargo build", "echo ready"]
+    );
+    assert!(workspaces.workspaces[1].init_commands.is_empty());
+
+    let registry = Registry::from_toml(&std::fs::read_to_string(&file_path).unwrap()).unwrap();
+    assert_eq!(registry.version, REGISTRY_VERSION);
+    assert!(backup_path.exists());
+
+    clear(file_path.to_str().unwrap());
+    clear(backup_path.to_str().unwrap());
+}
+
+#[test]
+pub fn detect_legacy_registry() {
+    // Legacy names that look like the start of a TOML key or table
+    let registry = Registry::parse("versions;/srv/v;sh\n[x];/srv/x;sh;make a=b\n").unwrap();
+    assert_eq!(registry.workspaces.len(), 2);
+    assert_eq!(registry.workspaces[1].name, "[x]");
+    assert!(fsck::check("versions;/srv/v;sh\n").legacy);
+
+    // A damaged TOML registry is never read as legacy lines
+    assert!(Registry::parse("version = 2\n[[workspaces]\nname = \"a;b;c\"\n").is_err());
+    assert!(!fsck::check("version = 2\n[[workspaces]\n").legacy);
+}
+
+#[test]
+pub fn commands_keep_semicolons() {
+    let registry = Registry {
+        workspaces: vec![Workspace::new(
+            "test".to_string(),
+            "te;st".to_string().into(),
+            "sh".to_string(),
+            vec!["echo a; echo b".to_string()],
+        )],
+        ..Registry::default()
     };
 
-    let file_path = file_config.test_workspaces_file;
+    let parsed = Registry::from_toml(&registry.to_toml().unwrap()).unwrap();
+    assert_eq!(parsed.workspaces[0].path, registry.workspaces[0].path);
+    assert_eq!(
+        parsed.workspaces[0].init_commands,
+        registry.workspaces[0].init_commands
+    );
+}
 
-    let workspaces = Workspaces::new(&file_path.as_str());
-    assert_eq!(workspaces.workspaces.len(), 0);
-    clear(file_path.as_str());
+#[test]
+pub fn concurrent_adds() {
+    let file_path = std::env::temp_dir().join("ws_concurrent_adds.toml");
+    clear(file_path.to_str().unwrap());
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let file_path = file_path.clone();
+            std::thread::spawn(move || {
+                let mut workspaces = Workspaces::new(file_path.to_str().unwrap()).unwrap();
+                let workspace = Workspace::new(
+                    format!("test{}", i),
+                    format!("test{}", i).into(),
+                    "sh".to_string(),
+                    vec![],
+                );
+                workspaces.add(workspace).unwrap();
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let workspaces = Workspaces::new(file_path.to_str().unwrap()).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 8);
+    clear(file_path.to_str().unwrap());
+}
+
+#[test]
+pub fn import_registry() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    let registry = Registry::from_legacy("api;/srv/api;sh;make\nweb;/srv/web;sh\n").unwrap();
+
+    assert_eq!(workspaces.import(&registry).unwrap(), 2);
+    assert_eq!(workspaces.import(&registry).unwrap(), 0);
+    assert_eq!(workspaces.workspaces.len(), 2);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+pub fn sqlite_store() {
+    let journal_path = std::env::temp_dir().join("ws_sqlite_journal.toml");
+    clear(journal_path.to_str().unwrap());
+    let mut workspaces = Workspaces::with_store(SqliteStore::open_in_memory().unwrap()).unwrap();
+    workspaces.journal = Some(Journal::new(&journal_path));
+    let api = Workspace::new(
+        "api".to_string(),
+        "/srv/api".to_string().into(),
+        "sh".to_string(),
+        vec!["make; make test".to_string()],
+    );
+    let web = Workspace::new(
+        "web".to_string(),
+        "/srv/web".to_string().into(),
+        "sh".to_string(),
+        vec![],
+    );
+    workspaces.add(api.clone()).unwrap();
+    workspaces.add(web.clone()).unwrap();
+
+    let found = workspaces.store.find_by_name("api").unwrap().unwrap();
+    assert_eq!(found.init_commands, api.init_commands);
+
+    // Changed rows are updated in place, so the registry order is kept
+    let api = workspaces.find("api").unwrap().clone();
+    workspaces.record_launch(&api).unwrap();
+    let patch = patch::Patch {
+        shell: Some("bash".to_string()),
+        ..patch::Patch::default()
+    };
+    workspaces.patch(&api.id, &patch).unwrap();
+    let registry = workspaces.store.load().unwrap();
+    assert_eq!(registry.workspaces, vec![api.clone(), web.clone()]);
+    assert_eq!(registry.workspaces[0].launch_count, 1);
+    assert_eq!(registry.workspaces[0].shell, "bash");
+
+    workspaces.remove_from_file(&api).unwrap();
+    let registry = workspaces.store.load().unwrap();
+    assert_eq!(registry.workspaces, vec![web.clone()]);
+    assert_eq!(registry.trash[0].workspace, api);
+
+    // Undoing the delete puts the row back where it was, as with a registry file
+    workspaces.undo(1).unwrap();
+    let registry = workspaces.store.load().unwrap();
+    assert_eq!(registry.workspaces, vec![api, web]);
+    assert!(registry.trash.is_empty());
+    clear(journal_path.to_str().unwrap());
+}
+
+#[test]
+pub fn load_named_workspace() {
+    let file_path = std::env::temp_dir().join("ws_load_named.toml");
+    clear(file_path.to_str().unwrap());
+    let mut workspaces = Workspaces::new(&file_path).unwrap();
+    for name in ["api", "web"] {
+        workspaces
+            .add(Workspace::new(
+                name.to_string(),
+                format!("/srv/{}", name).into(),
+                "sh".to_string(),
+                vec![],
+            ))
+            .unwrap();
+    }
+
+    let mut named = Workspaces::with_named(FileStore::new(&file_path), "api").unwrap();
+    assert_eq!(named.workspaces.len(), 1);
+    let api = named.find("api").unwrap().clone();
+    named.record_launch(&api).unwrap();
+    assert_eq!(named.find("api").unwrap().launch_count, 1);
+    assert!(Workspaces::with_named(FileStore::new(&file_path), "docs")
+        .unwrap()
+        .workspaces
+        .is_empty());
+
+    let registry = named.store.load().unwrap();
+    assert_eq!(registry.workspaces[0].name, "api");
+    assert_eq!(registry.workspaces[0].launch_count, 1);
+    assert_eq!(registry.workspaces[1].name, "web");
+    clear(file_path.to_str().unwrap());
+}
+
+#[test]
+pub fn discover_local_definition() {
+    let root = std::env::temp_dir().join("ws_discover_local");
+    let nested = root.join("src").join("deep");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(
+        root.join(LOCAL_FILE_NAME),
+        "name = \"project\"\ninit_commands = [\"make\"]\ntags = [\"backend\"]\n",
+    )
+    .unwrap();
+
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    workspaces.discover_local(&nested).unwrap();
+
+    let workspace = workspaces.find("project").unwrap();
+    assert_eq!(workspace.path, root);
+    assert_eq!(workspace.init_commands, vec!["make"]);
+    assert!(workspace.has_tags(&["backend".to_string()]));
+    assert!(workspaces.workspaces.is_empty());
+
+    // Misspelled fields aren't silently ignored
+    std::fs::write(
+        root.join(LOCAL_FILE_NAME),
+        "name = \"project\"\ntag = [\"x\"]\n",
+    )
+    .unwrap();
+    assert!(workspaces.discover_local(&nested).is_err());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+pub fn include_registries() {
+    let team = std::env::temp_dir().join("ws_include_team.toml");
+    let other = std::env::temp_dir().join("ws_include_other.toml");
+    std::fs::write(&team, "api;/srv/api;sh;make\nweb;/srv/web;sh\n").unwrap();
+    std::fs::write(&other, "web;/opt/web;bash\n").unwrap();
+
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    workspaces.include(&[&team]).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 2);
+
+    let api = workspaces.find("api").unwrap().clone();
+    assert_eq!(api.source, WorkspaceSource::Include(team.clone()));
+    assert!(workspaces.remove_from_file(&api).is_err());
+
+    // The same name in two included files needs a local override once it is used
+    workspaces.include(&[&team, &other]).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 2);
+    assert_eq!(
+        workspaces.conflict("web").unwrap().code,
+        ErrorCode::Ambiguous
+    );
+    assert!(workspaces.conflict("api").is_none());
+    workspaces
+        .add(Workspace::new(
+            "web".to_string(),
+            "/home/web".to_string().into(),
+            "sh".to_string(),
+            vec![],
+        ))
+        .unwrap();
+    assert!(workspaces.conflict("web").is_none());
+    let web = workspaces.find("web").unwrap();
+    assert_eq!(web.source, WorkspaceSource::Registry);
+    assert_eq!(web.path, std::path::PathBuf::from("/home/web"));
+
+    // Renaming an included workspace registers a copy under the new name
+    workspaces.rename(&api, "backend").unwrap();
+    let backend = workspaces.find("backend").unwrap();
+    assert_eq!(backend.source, WorkspaceSource::Registry);
+    assert_eq!(backend.path, api.path);
+    assert!(workspaces.find("api").is_some());
+
+    clear(team.to_str().unwrap());
+    clear(other.to_str().unwrap());
+}
+
+#[test]
+pub fn undo_changes() {
+    let journal_path = std::env::temp_dir().join("ws_undo_changes.toml");
+    clear(journal_path.to_str().unwrap());
+
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    workspaces.journal = Some(Journal::new(&journal_path));
+    // Clearing an empty registry changes nothing, so there is nothing to undo
+    assert_eq!(workspaces.clear().unwrap_err().code, ErrorCode::Empty);
+    for name in ["api", "web"] {
+        workspaces
+            .add(Workspace::new(
+                name.to_string(),
+                name.to_string().into(),
+                "sh".to_string(),
+                vec!["echo a; echo b".to_string()],
+            ))
+            .unwrap();
+    }
+    workspaces.clear().unwrap();
+    assert!(workspaces.workspaces.is_empty());
+
+    assert_eq!(workspaces.trash.len(), 2);
+
+    workspaces.undo(1).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 2);
+    assert!(workspaces.trash.is_empty());
+    assert_eq!(
+        workspaces.workspaces[0].init_commands,
+        vec!["echo a; echo b"]
+    );
+
+    // The clear is already reverted, so the next undo reverts the second add
+    workspaces.undo(1).unwrap();
+    assert_eq!(workspaces.workspaces.len(), 1);
+    assert!(workspaces.undo(2).is_err());
+
+    let entries = workspaces.journal.as_ref().unwrap().entries().unwrap();
+    assert_eq!(entries.len(), 5);
+    assert_eq!(entries[4].reverts, vec![2]);
+
+    // Undo only reverts what the entry changed, keeping what happened since
+    workspaces
+        .add(Workspace::new(
+            "docs".to_string(),
+            "docs".into(),
+            "sh".to_string(),
+            vec![],
+        ))
+        .unwrap();
+    let api = workspaces.find("api").unwrap().clone();
+    workspaces.record_launch(&api).unwrap();
+    workspaces.undo(1).unwrap();
+    assert!(workspaces.find("docs").is_none());
+    assert_eq!(workspaces.find("api").unwrap().launch_count, 1);
+
+    // A broken journal doesn't fail a change that was made
+    std::fs::write(&journal_path, "not a journal").unwrap();
+    workspaces
+        .add(Workspace::new(
+            "web".to_string(),
+            "web".into(),
+            "sh".to_string(),
+            vec![],
+        ))
+        .unwrap();
+    assert!(workspaces.find("web").is_some());
+    clear(journal_path.to_str().unwrap());
+}
+
+#[test]
+pub fn trash_restore_and_purge() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    let workspace = Workspace::new(
+        "test".to_string(),
+        "test".to_string().into(),
+        "sh".to_string(),
+        vec![],
+    );
+    workspaces.add(workspace).unwrap();
+    let workspace = workspaces.workspaces[0].clone();
+    workspaces.remove_from_file(&workspace).unwrap();
+    assert!(workspaces.workspaces.is_empty());
+    assert_eq!(workspaces.trash[0].workspace, workspace);
+
+    workspaces.restore("test").unwrap();
+    assert_eq!(workspaces.workspaces, vec![workspace.clone()]);
+    assert!(workspaces.trash.is_empty());
+    assert!(workspaces.restore("test").is_err());
+
+    workspaces.clear().unwrap();
+    // Nothing was deleted more than an hour ago
+    assert!(workspaces.purge(Some(3600)).unwrap().is_empty());
+    assert_eq!(workspaces.purge(None).unwrap().len(), 1);
+    assert!(workspaces.store.load().unwrap().trash.is_empty());
+}
+
+#[test]
+pub fn fsck_reports_and_repairs() {
+    let check = fsck::check(
+        "api;/srv/api;bash\nweb;/srv/web;cargo run\nbroken\napi;/srv/other;sh\ndocs;/srv/docs\n",
+    );
+    assert!(check.legacy);
+    assert!(!check.is_unreadable());
+
+    let lines: Vec<(usize, Option<Repair>)> = check
+        .issues
+        .iter()
+        .map(|issue| (issue.line, issue.repair.clone()))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![
+            (2, Some(Repair::UnshiftShell)),
+            (3, Some(Repair::Remove)),
+            (4, Some(Repair::Rename("api-2".to_string()))),
+            (5, Some(Repair::DefaultShell)),
+        ]
+    );
+
+    // Leave the duplicate name alone
+    let registry = check.repair(&[0, 1, 3]);
+    let names: Vec<&str> = registry
+        .workspaces
+        .iter()
+        .map(|w| w.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["api", "web", "api", "docs"]);
+    assert_eq!(registry.workspaces[1].shell, Shell::default_name());
+    assert_eq!(registry.workspaces[1].init_commands, vec!["cargo run"]);
+    assert_eq!(registry.workspaces[3].shell, Shell::default_name());
+
+    let contents = registry.to_toml().unwrap();
+    let third = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| *line == "[[workspaces]]")
+        .nth(2)
+        .map(|(index, _)| index + 1);
+    let check = fsck::check(&contents);
+    assert_eq!(check.issues.len(), 1);
+    assert_eq!(Some(check.issues[0].line), third);
+
+    // A single word is a command too unless it names a shell
+    let check = fsck::check("build;/srv/build;make\ncli;/srv/cli;/bin/sh\n");
+    let repairs: Vec<Option<Repair>> = check.issues.iter().map(|i| i.repair.clone()).collect();
+    assert_eq!(repairs, vec![Some(Repair::UnshiftShell)]);
+    assert_eq!(check.issues[0].line, 1);
+}
+
+#[test]
+pub fn fsck_reports_unreadable_toml() {
+    let check = fsck::check("version = 1\n\n[[workspaces]]\nname = \"api\n");
+    assert!(check.is_unreadable());
+    assert_eq!(check.issues[0].line, 4);
+}
+
+#[test]
+pub fn select_profiles() {
+    let mut file_config: FileConfig = toml::from_str(
+        r#"
+        save_dir = "/ws"
+        workspaces_file = "/ws/workspaces.txt"
+        test_workspaces_file = "/ws/test_workspaces.txt"
+        profile = "work"
+
+        [profiles.work]
+        workspaces_file = "/ws/work.toml"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(file_config.profile_name(), "work");
+    assert_eq!(
+        file_config.current_profile().unwrap().workspaces_file,
+        std::path::Path::new("/ws/work.toml")
+    );
+    assert_eq!(
+        file_config.journal_file(),
+        std::path::Path::new("/ws/journal-work.toml")
+    );
+
+    file_config.selected_profile = Some(DEFAULT_PROFILE.to_string());
+    assert_eq!(
+        file_config.current_profile().unwrap().workspaces_file,
+        std::path::Path::new("/ws/workspaces.txt")
+    );
+    assert_eq!(
+        file_config.journal_file(),
+        std::path::Path::new("/ws/journal.toml")
+    );
+
+    file_config.selected_profile = Some("oncall".to_string());
+    assert!(file_config.current_profile().is_err());
+}
+
+#[test]
+pub fn edit_configuration() {
+    let file_path = std::env::temp_dir().join("ws_edit_configuration.toml");
+    let original = r#"# My setup
+save_dir = "/ws"
+workspaces_file = "/ws/workspaces.txt"
+test_workspaces_file = "/ws/test_workspaces.txt"
+includes = ["/team/b.toml", "/team/a.toml"] # b wins
+
+[remaps.laptop]
+"/srv" = "/home/me/srv"
+"#;
+    std::fs::write(&file_path, original).unwrap();
+
+    let profile = Profile {
+        workspaces_file: "/ws/work.toml".into(),
+        database_file: None,
+    };
+    FileConfig::add_profile(&file_path, "work", &profile).unwrap();
+    FileConfig::set_profile(&file_path, Some("work")).unwrap();
+
+    let contents = std::fs::read_to_string(&file_path).unwrap();
+    assert!(contents.starts_with("# My setup\n"));
+    assert!(contents.contains(r#"includes = ["/team/b.toml", "/team/a.toml"] # b wins"#));
+    let file_config: FileConfig = toml::from_str(&contents).unwrap();
+    assert_eq!(file_config.profile_name(), "work");
+    assert_eq!(
+        file_config.current_profile().unwrap().workspaces_file,
+        std::path::Path::new("/ws/work.toml")
+    );
+
+    FileConfig::set_profile(&file_path, None).unwrap();
+    let file_config: FileConfig =
+        toml::from_str(&std::fs::read_to_string(&file_path).unwrap()).unwrap();
+    assert_eq!(file_config.profile_name(), DEFAULT_PROFILE);
+    assert!(file_config.profiles.contains_key("work"));
+    clear(file_path.to_str().unwrap());
+    clear(&format!("{}.lock", file_path.display()));
+}
+
+#[test]
+pub fn ids_keep_operations_apart() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    for name in ["api", "web"] {
+        workspaces
+            .add(Workspace::new(
+                name.to_string(),
+                "/srv/shared".to_string().into(),
+                "sh".to_string(),
+                vec![],
+            ))
+            .unwrap();
+    }
+    assert!(workspaces
+        .add(Workspace::new(
+            "api".to_string(),
+            "/srv/other".to_string().into(),
+            "sh".to_string(),
+            vec![],
+        ))
+        .is_err());
+
+    let api = workspaces.find("api").unwrap().clone();
+    let web = workspaces.find("web").unwrap().clone();
+    assert!(!api.id.is_empty());
+    assert_ne!(api.id, web.id);
+
+    // Sharing a path with the deleted workspace no longer deletes web too
+    workspaces.remove_from_file(&api).unwrap();
+    assert_eq!(workspaces.workspaces, vec![web.clone()]);
+
+    let renamed = Workspace::new(
+        "site".to_string(),
+        "/srv/site".to_string().into(),
+        "bash".to_string(),
+        vec![],
+    );
+    workspaces.replace(&web.id, renamed).unwrap();
+    assert_eq!(workspaces.workspaces[0].id, web.id);
+    assert_eq!(workspaces.workspaces[0].name, "site");
+}
+
+#[test]
+pub fn upgrade_assigns_ids() {
+    let mut registry = Registry::from_toml(
+        "version = 1\n\n[[workspaces]]\nname = \"api\"\npath = \"/srv/api\"\nshell = \"sh\"\n",
+    )
+    .unwrap();
+    assert!(registry.workspaces[0].id.is_empty());
+
+    assert!(registry.upgrade());
+    assert_eq!(registry.version, REGISTRY_VERSION);
+    assert!(!registry.workspaces[0].id.is_empty());
+    assert!(!registry.upgrade());
+}
+
+#[test]
+pub fn normalize_and_expand_paths() {
+    use std::path::Path;
+
+    let base = Path::new("/srv/projects");
+    assert_eq!(
+        paths::normalize(Path::new("./api/../web"), base),
+        Path::new("/srv/projects/web")
+    );
+    assert_eq!(
+        paths::normalize(Path::new("/srv/./api/"), base),
+        Path::new("/srv/api")
+    );
+    assert_eq!(
+        paths::normalize(Path::new("~/api"), base),
+        Path::new("~/api")
+    );
+    assert_eq!(
+        paths::normalize(Path::new("${WS_TEST_ROOT}/api"), base),
+        Path::new("${WS_TEST_ROOT}/api")
+    );
+
+    std::env::set_var("WS_TEST_ROOT", "/mnt/code");
+    assert_eq!(
+        paths::expand(Path::new("${WS_TEST_ROOT}/api")).unwrap(),
+        Path::new("/mnt/code/api")
+    );
+    assert_eq!(
+        paths::expand(Path::new("$WS_TEST_ROOT/a$b$")).unwrap_err(),
+        "Environment variable b is not set"
+    );
+    assert_eq!(
+        paths::expand(Path::new("/srv/$/api")).unwrap(),
+        Path::new("/srv/$/api")
+    );
+    assert!(paths::expand(Path::new("~/api")).unwrap().is_absolute());
+    assert_eq!(
+        paths::expand(Path::new("~user/api")).unwrap(),
+        Path::new("~user/api")
+    );
+}
+
+#[test]
+pub fn remap_paths_per_host() {
+    use std::path::Path;
+
+    let file_config: FileConfig = toml::from_str(
+        r#"
+        save_dir = "/ws"
+        workspaces_file = "/ws/workspaces.txt"
+        test_workspaces_file = "/ws/test_workspaces.txt"
+
+        [remaps.BuildBox]
+        "/Users/me" = "/home/me"
+        "/Users/me/code" = "/src"
+
+        [remaps.laptop]
+        "/home/me" = "/Users/me"
+        "#,
+    )
+    .unwrap();
+    assert!(file_config.remaps_for("desktop").is_empty());
+
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    for (name, path) in [("api", "/Users/me/code/api"), ("notes", "/Users/me/notes")] {
+        workspaces
+            .add(Workspace::new(
+                name.to_string(),
+                path.into(),
+                "sh".to_string(),
+                vec![],
+            ))
+            .unwrap();
+    }
+    workspaces.set_remaps(file_config.remaps_for("buildbox"));
+
+    // The longest matching prefix wins and the stored path is left alone
+    let api = workspaces.find("api").unwrap();
+    assert_eq!(workspaces.resolve(api).unwrap(), Path::new("/src/api"));
+    assert_eq!(api.path, Path::new("/Users/me/code/api"));
+    let notes = workspaces.find("notes").unwrap();
+    assert_eq!(
+        workspaces.resolve(notes).unwrap(),
+        Path::new("/home/me/notes")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+pub fn non_utf8_paths_round_trip() {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+    let path = PathBuf::from(OsString::from_vec(b"/srv/caf\xe9".to_vec()));
+    let command = OsString::from_vec(b"/srv/caf\xe9/init.sh".to_vec());
+    let file_path = std::env::temp_dir().join("ws_non_utf8.toml");
+    clear(file_path.to_str().unwrap());
+
+    let mut workspaces = Workspaces::new(&file_path).unwrap();
+    workspaces
+        .add(Workspace {
+            init_commands: vec![command.clone(), "make".into()],
+            ..Workspace::new("cafe".to_string(), path.clone(), "sh".to_string(), vec![])
+        })
+        .unwrap();
+
+    let workspaces = Workspaces::new(&file_path).unwrap();
+    assert_eq!(workspaces.workspaces[0].path, path);
+    assert_eq!(
+        workspaces.workspaces[0].init_commands,
+        vec![command, "make".into()]
+    );
+    // Valid UTF-8 stays readable
+    assert!(std::fs::read_to_string(&file_path)
+        .unwrap()
+        .contains(r#""make""#));
+
+    clear(file_path.to_str().unwrap());
+}
+
+#[test]
+pub fn record_usage() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    workspaces
+        .add(Workspace::new(
+            "api".to_string(),
+            "/srv/api".to_string().into(),
+            "sh".to_string(),
+            vec![],
+        ))
+        .unwrap();
+    let api = workspaces.find("api").unwrap().clone();
+    assert!(api.created_at.is_some());
+    assert_eq!(api.last_used_at, None);
+
+    workspaces.record_launch(&api).unwrap();
+    workspaces.record_launch(&api).unwrap();
+    let launched = workspaces.find("api").unwrap().clone();
+    assert_eq!(launched.launch_count, 2);
+    assert!(launched.last_used_at.is_some());
+
+    // Modifying a workspace keeps its usage
+    let modified = Workspace::new(
+        "api".to_string(),
+        "/srv/api2".to_string().into(),
+        "sh".to_string(),
+        vec![],
+    );
+    workspaces.replace(&api.id, modified).unwrap();
+    let stored = &workspaces.store.load().unwrap().workspaces[0];
+    assert_eq!(stored.launch_count, 2);
+    assert_eq!(stored.created_at, api.created_at);
+}
+
+#[test]
+pub fn jump_ranking() {
+    let now = 100_000_000;
+    let workspace =
+        |name: &str, path: &str, launch_count: u64, last_used_at: Option<u64>| Workspace {
+            launch_count,
+            last_used_at,
+            ..Workspace::new(name.to_string(), path.into(), "sh".to_string(), vec![])
+        };
+    let workspaces = vec![
+        workspace("api-server", "/srv/api", 1, Some(now - 30 * 24 * 60 * 60)),
+        workspace("api-client", "/srv/client", 20, Some(now - 60)),
+        workspace("web", "/srv/web", 0, None),
+        workspace("docs", "/home/me/api-docs", 0, None),
+    ];
+    let query = |terms: &[&str]| -> Vec<String> { terms.iter().map(|t| t.to_string()).collect() };
+
+    // The frequently used workspace wins among similar matches
+    let candidates = jump::rank(&query(&["api"]), &workspaces, now);
+    let names: Vec<&str> = candidates
+        .iter()
+        .map(|c| c.workspace.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["api-client", "api-server", "docs"]);
+    assert_eq!(
+        jump::best(&query(&["api"]), &candidates).unwrap().name,
+        "api-client"
+    );
+
+    // Subsequences and several terms
+    let candidates = jump::rank(&query(&["asrv"]), &workspaces, now);
+    assert_eq!(candidates[0].workspace.name, "api-server");
+    let candidates = jump::rank(&query(&["api", "srv/api"]), &workspaces, now);
+    assert_eq!(candidates.len(), 1);
+
+    // Similar scores are ambiguous, unless one is an exact name
+    let candidates = jump::rank(&query(&["s"]), &workspaces[..1], now);
+    assert!(jump::best(&query(&["s"]), &candidates).is_some());
+    let tied = vec![
+        workspace("one", "/srv/x1", 0, None),
+        workspace("two", "/srv/x2", 0, None),
+    ];
+    let candidates = jump::rank(&query(&["x"]), &tied, now);
+    assert!(jump::best(&query(&["x"]), &candidates).is_none());
+    let candidates = jump::rank(&query(&["two"]), &tied, now);
+    assert_eq!(
+        jump::best(&query(&["two"]), &candidates).unwrap().name,
+        "two"
+    );
+}
+
+#[test]
+pub fn picker_filter_and_numbered_prompt() {
+    let workspaces: Vec<Workspace> = ["api", "web", "webapp"]
+        .iter()
+        .map(|name| {
+            Workspace::new(
+                name.to_string(),
+                format!("/srv/{}", name).into(),
+                "sh".to_string(),
+                vec![],
+            )
+        })
+        .collect();
+
+    let names = |matches: Vec<&Workspace>| -> Vec<String> {
+        matches.iter().map(|w| w.name.clone()).collect()
+    };
+    assert_eq!(
+        names(picker::filter("", &workspaces)),
+        vec!["api", "web", "webapp"]
+    );
+    assert_eq!(names(picker::filter("wap", &workspaces)), vec!["webapp"]);
+    assert_eq!(
+        names(picker::filter("web", &workspaces)),
+        vec!["web", "webapp"]
+    );
+
+    let picked = picker::pick_numbered(&workspaces, &mut "2\n".as_bytes()).unwrap();
+    assert_eq!(picked.unwrap().name, "web");
+    assert!(picker::pick_numbered(&workspaces, &mut "\n".as_bytes())
+        .unwrap()
+        .is_none());
+    assert!(picker::pick_numbered(&workspaces, &mut "4\n".as_bytes()).is_err());
+}
+
+#[test]
+pub fn tagged_workspaces() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    for (name, tags) in [
+        ("api", vec!["backend", "client-x"]),
+        ("db", vec!["backend"]),
+        ("site", vec!["oss"]),
+    ] {
+        workspaces
+            .add(Workspace {
+                tags: tags.into_iter().map(|t| t.to_string()).collect(),
+                ..Workspace::new(
+                    name.to_string(),
+                    format!("/srv/{}", name).into(),
+                    "sh".to_string(),
+                    vec![],
+                )
+            })
+            .unwrap();
+    }
+
+    let api = workspaces.find("api").unwrap();
+    assert!(api.has_tags(&[]));
+    assert!(api.has_tags(&["backend".to_string(), "client-x".to_string()]));
+    assert!(!api.has_tags(&["backend".to_string(), "oss".to_string()]));
+    assert_eq!(workspaces.store.load().unwrap().workspaces[0].tags.len(), 2);
+
+    // All workspaces with the tag go to the trash in one undoable change
+    let removed = workspaces.remove_tagged(&["backend".to_string()]).unwrap();
+    assert_eq!(removed.len(), 2);
+    let names: Vec<&str> = workspaces
+        .workspaces
+        .iter()
+        .map(|w| w.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["site"]);
+    assert_eq!(workspaces.trash.len(), 2);
+}
+
+#[test]
+pub fn list_filters() {
+    assert!(filter::glob_match("api-*", "api-server"));
+    assert!(filter::glob_match("*-?pi", "web-api"));
+    assert!(filter::glob_match("a*b*c", "abxbyc"));
+    assert!(!filter::glob_match("api-*", "web-api"));
+    assert!(!filter::glob_match("a?", "a"));
+
+    let workspace = Workspace {
+        tags: vec!["backend".to_string()],
+        ..Workspace::new(
+            "api-server".to_string(),
+            "~/src/api".into(),
+            "zsh".to_string(),
+            vec!["cargo build".to_string()],
+        )
+    };
+    let resolved = std::path::Path::new("/home/me/src/api");
+    assert!(filter::Filter::default().matches(&workspace, resolved));
+
+    let filter = filter::Filter {
+        name: Some("api-*".to_string()),
+        path: Some("/home/me/src".into()),
+        shell: Some("zsh".to_string()),
+        command: Some("cargo".to_string()),
+        tags: vec!["backend".to_string()],
+    };
+    assert!(filter.matches(&workspace, resolved));
+    // Path prefixes match whole components
+    assert!(!filter::Filter {
+        path: Some("/home/me/sr".into()),
+        ..filter::Filter::default()
+    }
+    .matches(&workspace, resolved));
+    assert!(!filter::Filter {
+        command: Some("npm".to_string()),
+        ..filter::Filter::default()
+    }
+    .matches(&workspace, resolved));
+}
+
+#[test]
+pub fn json_documents() {
+    output::set_json(true);
+    output::message("Moved workspace api to the trash".to_string());
+    output::warn("broken .workspace.toml");
+    output::data(&vec!["api"]);
+    let document: serde_json::Value =
+        serde_json::from_str(&output::document(Some("delete"), &Ok(()))).unwrap();
+    assert_eq!(document["command"], "delete");
+    assert_eq!(document["ok"], true);
+    assert_eq!(document["data"], serde_json::json!(["api"]));
+    assert_eq!(document["messages"][0], "Moved workspace api to the trash");
+    assert_eq!(document["warnings"][0], "broken .workspace.toml");
+
+    // Errors carry a stable code, and every document starts over
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    let workspace = Workspace::new(
+        "api".to_string(),
+        "/srv/api".into(),
+        "sh".to_string(),
+        vec![],
+    );
+    workspaces.add(workspace.clone()).unwrap();
+    let error = workspaces.add(workspace).unwrap_err();
+    assert_eq!(error.code, ErrorCode::AlreadyExists);
+    let document: serde_json::Value =
+        serde_json::from_str(&output::document(Some("add"), &Err(error))).unwrap();
+    assert_eq!(document["ok"], false);
+    assert_eq!(document["error"]["code"], "already_exists");
+    assert_eq!(document["error"]["severity"], "error");
+    assert_eq!(document["data"], serde_json::Value::Null);
+    assert_eq!(document["messages"], serde_json::json!([]));
+    output::set_json(false);
+}
+
+#[test]
+pub fn patch_workspace() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    workspaces
+        .add(Workspace {
+            tags: vec!["backend".to_string()],
+            ..Workspace::new(
+                "api".to_string(),
+                "/srv/api".into(),
+                "sh".to_string(),
+                vec!["one".to_string(), "two".to_string(), "three".to_string()],
+            )
+        })
+        .unwrap();
+    let api = workspaces.find("api").unwrap().clone();
+
+    let patch = patch::Patch {
+        shell: Some("zsh".to_string()),
+        remove_commands: vec![3, 1],
+        insert_commands: vec![(2, "inserted".into())],
+        move_commands: vec![(2, 1)],
+        add_commands: vec!["last".into()],
+        set_env: vec![("RUST_LOG".to_string(), "debug".to_string())],
+        ..patch::Patch::default()
+    };
+    workspaces.patch(&api.id, &patch).unwrap();
+
+    // Everything not in the patch is kept
+    let stored = workspaces.store.load().unwrap().workspaces[0].clone();
+    assert_eq!(stored.shell, "zsh");
+    assert_eq!(stored.init_commands, vec!["inserted", "two", "last"]);
+    assert_eq!(stored.env["RUST_LOG"], "debug");
+    assert_eq!(stored.path, std::path::Path::new("/srv/api"));
+    assert_eq!(stored.tags, vec!["backend"]);
+    assert_eq!(stored.id, api.id);
+
+    // Out of range positions change nothing
+    let bad = patch::Patch {
+        shell: Some("bash".to_string()),
+        remove_commands: vec![4],
+        ..patch::Patch::default()
+    };
+    assert!(workspaces.patch(&api.id, &bad).is_err());
+    assert_eq!(workspaces.find("api").unwrap().shell, "zsh");
+}
+
+#[test]
+pub fn rename_workspace() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    for name in ["api", "web"] {
+        workspaces
+            .add(Workspace::new(
+                name.to_string(),
+                format!("/srv/{}", name).into(),
+                "sh".to_string(),
+                vec![],
+            ))
+            .unwrap();
+    }
+    let api = workspaces.find("api").unwrap().clone();
+    workspaces.record_launch(&api).unwrap();
+
+    // Names stay unique
+    let error = workspaces.rename(&api, "web").unwrap_err();
+    assert_eq!(error.code, ErrorCode::AlreadyExists);
+
+    workspaces.rename(&api, "backend").unwrap();
+    assert!(workspaces.find("api").is_none());
+    let renamed = workspaces.find("backend").unwrap();
+    assert_eq!(renamed.id, api.id);
+    assert_eq!(renamed.created_at, api.created_at);
+    assert_eq!(renamed.launch_count, 1);
+}
+
+#[test]
+pub fn edit_definition() {
+    let mut workspace = Workspace::new(
+        "api".to_string(),
+        "/srv/api".into(),
+        "sh".to_string(),
+        vec!["echo one".into()],
+    );
+    workspace.tags = vec!["backend".to_string()];
+    let toml = edit::to_toml(&workspace).unwrap();
+
+    let base = std::path::Path::new("/home");
+    let edited = toml.replace("\"sh\"", "\"zsh\"").replace("/srv/api", "api");
+    let parsed = edit::parse(&edited, &workspace, base).unwrap().unwrap();
+    assert_eq!(parsed.shell, "zsh");
+    assert_eq!(parsed.path, std::path::Path::new("/home/api"));
+    assert_eq!(parsed.init_commands, workspace.init_commands);
+    assert_eq!(parsed.tags, workspace.tags);
+    assert_eq!(parsed.id, workspace.id);
+    assert_eq!(parsed.created_at, workspace.created_at);
+
+    // Deleting everything but comments cancels
+    assert!(edit::parse("# nothing\n\n", &workspace, base)
+        .unwrap()
+        .is_none());
+
+    let bad_tag = toml.replace("\"backend\"", "\"a b\"");
+    assert!(edit::parse(&bad_tag, &workspace, base).is_err());
+    let no_name = toml.replace("name = \"api\"", "name = \"\"");
+    assert!(edit::parse(&no_name, &workspace, base).is_err());
+    let unknown = format!("color = \"red\"\n{}", toml);
+    assert!(edit::parse(&unknown, &workspace, base).is_err());
+}
+
+#[test]
+pub fn add_wizard() {
+    let mut workspaces = Workspaces::with_store(MemoryStore::default()).unwrap();
+    workspaces
+        .add(Workspace::new(
+            "api".to_string(),
+            "/srv/api".into(),
+            "sh".to_string(),
+            vec![],
+        ))
+        .unwrap();
+    let dir = std::env::temp_dir();
+    let tags = vec!["backend".to_string()];
+
+    // A taken name and a missing directory are asked for again, empty answers take defaults
+    let mut input =
+        std::io::Cursor::new("\napi\nweb\n/does/not/exist\n\n\ncargo build\ncargo test\n\ny\n");
+    let workspace = wizard::ask_workspace(&workspaces, &dir, "zsh", &tags, &mut input)
+        .unwrap()
+        .unwrap();
+    assert_eq!(workspace.name, "web");
+    assert_eq!(workspace.path, dir);
+    assert_eq!(workspace.shell, "zsh");
+    assert_eq!(workspace.init_commands, vec!["cargo build", "cargo test"]);
+    assert_eq!(workspace.tags, tags);
+
+    // Placeholders are checked expanded but kept as typed
+    let mut home = std::io::Cursor::new("web\n$WS_WIZARD_UNSET/x\n~\n\n\ny\n");
+    let workspace = wizard::ask_workspace(&workspaces, &dir, "zsh", &[], &mut home)
+        .unwrap()
+        .unwrap();
+    assert_eq!(workspace.path, std::path::Path::new("~"));
+
+    let mut declined = std::io::Cursor::new("web\n\nbash\n\nn\n");
+    assert!(
+        wizard::ask_workspace(&workspaces, &dir, "zsh", &[], &mut declined)
+            .unwrap()
+            .is_none()
+    );
+
+    let mut closed = std::io::Cursor::new("web\n");
+    let error = wizard::ask_workspace(&workspaces, &dir, "zsh", &[], &mut closed).unwrap_err();
+    assert_eq!(error.code, ErrorCode::Cancelled);
+}
+
+#[test]
+pub fn find_shell_executable() {
+    let current = std::env::current_exe().unwrap();
+    let given = current.to_str().unwrap();
+    assert_eq!(Shell::find_executable(given), Some(current.clone()));
+
+    assert_eq!(Shell::find_executable("/does/not/exist/sh"), None);
+    assert_eq!(Shell::find_executable("no-such-shell-anywhere"), None);
 }
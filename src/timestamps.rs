@@ -0,0 +1,17 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The current time in seconds since the Unix epoch, the unit timestamps are stored in
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+pub fn to_system_time(timestamp: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(timestamp)
+}
+
+/// Formats a stored timestamp as RFC 3339, e.g. `2023-04-08T17:02:11Z`
+pub fn format(timestamp: u64) -> String {
+    humantime::format_rfc3339_seconds(to_system_time(timestamp)).to_string()
+}
@@ -0,0 +1,104 @@
+use crate::{paths, ErrorCode, Severity, Workspace, WorkspaceError, Workspaces};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+/// Asks for a new workspace step by step on `input`, with paths relative to `current_dir` and
+/// `shell` as the default shell. Returns `None` if the user doesn't confirm it.
+pub fn ask_workspace(
+    workspaces: &Workspaces,
+    current_dir: &Path,
+    shell: &str,
+    tags: &[String],
+    input: &mut dyn BufRead,
+) -> Result<Option<Workspace>, WorkspaceError> {
+    let name = loop {
+        let name = prompt("Name", None, input)?;
+        if name.is_empty() {
+            println!("The workspace needs a name");
+        } else if workspaces.find(&name).is_some() {
+            println!("Workspace {} already exists", name);
+        } else {
+            break name;
+        }
+    };
+
+    let default_path = current_dir.display().to_string();
+    let path = loop {
+        let path = paths::normalize(
+            Path::new(&prompt("Path", Some(&default_path), input)?),
+            current_dir,
+        );
+        // `~` and environment variables are kept in the registry, so only the check expands them
+        if paths::expand(&path).is_ok_and(|expanded| expanded.is_dir()) {
+            break path;
+        }
+        println!("{} is not a directory", path.display());
+    };
+
+    let shell = prompt("Shell", Some(shell), input)?;
+
+    println!("Init commands, one per line (empty line to finish):");
+    let mut init_commands = vec![];
+    loop {
+        let command = prompt(&format!("{}", init_commands.len() + 1), None, input)?;
+        if command.is_empty() {
+            break;
+        }
+        init_commands.push(command);
+    }
+
+    let workspace = Workspace {
+        tags: tags.to_vec(),
+        ..Workspace::new(name, path, shell, init_commands)
+    };
+    println!();
+    print_summary(&workspace);
+    let answer = prompt("Add this workspace? (y/n)", None, input)?;
+    Ok(matches!(answer.as_str(), "y" | "yes").then_some(workspace))
+}
+
+/// Reads one trimmed line, or `default` for an empty one. The end of the input cancels.
+fn prompt(
+    question: &str,
+    default: Option<&str>,
+    input: &mut dyn BufRead,
+) -> Result<String, WorkspaceError> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush().map_err(|e| e.to_string())?;
+
+    let mut answer = String::new();
+    if input.read_line(&mut answer).map_err(|e| e.to_string())? == 0 {
+        println!();
+        return Err(
+            WorkspaceError::new("Workspace not added".to_string(), Severity::Message)
+                .with_code(ErrorCode::Cancelled),
+        );
+    }
+    let answer = answer.trim();
+    Ok(match default {
+        Some(default) if answer.is_empty() => default.to_string(),
+        _ => answer.to_string(),
+    })
+}
+
+fn print_summary(workspace: &Workspace) {
+    println!("Name:  {}", workspace.name);
+    println!("Path:  {}", workspace.path.display());
+    println!("Shell: {}", workspace.shell);
+    if !workspace.tags.is_empty() {
+        println!("Tags:  {}", workspace.tags.join(", "));
+    }
+    if workspace.init_commands.is_empty() {
+        println!("No init commands");
+    } else {
+        println!("Init commands:");
+        for (index, command) in workspace.init_commands.iter().enumerate() {
+            println!("  {}. {}", index + 1, command.to_string_lossy());
+        }
+    }
+}
@@ -1,16 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
+use std::ffi::OsString;
 use std::fmt::{Debug, Formatter};
 use std::fs;
-use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-use crate::Shell;
+use crate::{
+    local,
+    output::{self, say},
+    patch::Patch,
+    paths,
+    paths::Remap,
+    timestamps, ErrorCode, FileStore, Journal, JournalEntry, JournalLock, Registry, Shell,
+    TrashedWorkspace, WorkspaceError, WorkspaceStore,
+};
 
+/// Where a workspace was loaded from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WorkspaceSource {
+    /// The personal registry, the only one `ws` writes to
+    #[default]
+    Registry,
+    /// A read-only registry listed in `includes`
+    Include(PathBuf),
+    /// A `.workspace.toml` above the current directory
+    Local(PathBuf),
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct Workspace {
+    /// Generated when the workspace is registered and never changed, so operations can't hit
+    /// another entry. Included and local workspaces have none.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
     pub name: String,
+    #[serde(with = "paths::os_string")]
     pub path: PathBuf,
     pub shell: String,
-    pub init_commands: Vec<String>,
+    #[serde(default, with = "paths::os_strings")]
+    pub init_commands: Vec<OsString>,
+    /// Environment variables set for the init commands and the shell
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Labels such as `backend` or `client-x` for acting on groups of workspaces
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// When the workspace was registered, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>,
+    /// When the workspace was last initialized, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<u64>,
+    /// How many times the workspace has been initialized
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub launch_count: u64,
+    #[serde(skip)]
+    pub source: WorkspaceSource,
 }
 
 impl Workspace {
@@ -21,50 +68,101 @@ impl Workspace {
         init_commands: Vec<String>,
     ) -> Workspace {
         Workspace {
+            id: String::new(),
             name,
             path,
             shell,
-            init_commands,
+            init_commands: init_commands.into_iter().map(OsString::from).collect(),
+            env: BTreeMap::new(),
+            tags: Vec::new(),
+            created_at: None,
+            last_used_at: None,
+            launch_count: 0,
+            source: WorkspaceSource::Registry,
         }
     }
 
-    pub fn init(&self) {
-        println!(
-            "Initializing workspace {} at {}",
-            self.name,
-            self.path.display()
-        );
+    pub fn is_read_only(&self) -> bool {
+        matches!(self.source, WorkspaceSource::Include(_))
+    }
+
+    /// The path with `~` and environment variables expanded, which is what the workspace
+    /// actually refers to on this machine
+    pub fn expanded_path(&self) -> Result<PathBuf, WorkspaceError> {
+        paths::expand(&self.path).map_err(|e| {
+            format!(
+                "Could not expand the path of workspace {}: {}",
+                self.name, e
+            )
+            .into()
+        })
+    }
+
+    /// Whether the workspace has every one of `tags`
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+
+    /// Runs the init commands and opens the shell in `path`, the workspace's path resolved for
+    /// this machine
+    pub fn init(&self, path: &Path) -> Result<(), WorkspaceError> {
+        say!("Initializing workspace {} at {}", self.name, path.display());
+        self.run_init_commands()?;
+
+        // Execute an interactive shell in the workspace directory
+        let shell = Shell {
+            env: self.env.clone(),
+            ..Shell::new(path, &self.shell)
+        };
+        shell.get_input()
+    }
 
+    /// Runs the init commands without opening a shell
+    pub fn run_init_commands(&self) -> Result<(), WorkspaceError> {
         for command in &self.init_commands {
             // run the command in the corresponding terminal to the os
-            if cfg!(target_os = "windows") {
-                let output = std::process::Command::new("cmd")
-                    .args(&["/C", command])
+            let output = if cfg!(target_os = "windows") {
+                std::process::Command::new("cmd")
+                    .arg("/C")
+                    .arg(command)
+                    .envs(&self.env)
                     .output()
-                    .expect("failed to execute process");
-                println!("{}", String::from_utf8_lossy(&output.stdout));
             } else {
-                let output = std::process::Command::new("sh")
+                std::process::Command::new("sh")
                     .arg("-c")
                     .arg(command)
+                    .envs(&self.env)
                     .output()
-                    .expect("failed to execute process");
-                println!("{}", String::from_utf8_lossy(&output.stdout));
-            }
+            };
+            let output = output.map_err(|e| {
+                WorkspaceError::coded(
+                    ErrorCode::Io,
+                    format!(
+                        "Could not run init command {:?} of workspace {}: {}",
+                        command, self.name, e
+                    ),
+                )
+            })?;
+            say!("{}", String::from_utf8_lossy(&output.stdout));
         }
-        // Execute an interactive shell in the workspace directory
-        let shell = Shell::new(&self.path, &self.shell);
-        shell.get_input();
+        Ok(())
     }
 }
 
 impl Clone for Workspace {
     fn clone(&self) -> Self {
         Workspace {
+            id: self.id.clone(),
             name: self.name.clone(),
             path: self.path.clone(),
             shell: self.shell.clone(),
             init_commands: self.init_commands.clone(),
+            env: self.env.clone(),
+            tags: self.tags.clone(),
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+            launch_count: self.launch_count,
+            source: self.source.clone(),
         }
     }
 }
@@ -78,138 +176,676 @@ impl PartialEq for Workspace {
 impl Debug for Workspace {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Workspace")
+            .field("id", &self.id)
             .field("name", &self.name)
             .field("path", &self.path)
             .field("shell", &self.shell)
             .field("init_commands", &self.init_commands)
+            .field("env", &self.env)
+            .field("tags", &self.tags)
+            .field("created_at", &self.created_at)
+            .field("last_used_at", &self.last_used_at)
+            .field("launch_count", &self.launch_count)
+            .field("source", &self.source)
             .finish()
     }
 }
 
 pub struct Workspaces {
     pub active_workspace: Option<Workspace>,
+    /// The registered workspaces followed by the included ones they don't override
     pub workspaces: Vec<Workspace>,
-    pub workspace_file: String,
+    /// Every workspace read from the included registries
+    pub included: Vec<Workspace>,
+    /// Deleted workspaces of the personal registry, oldest first
+    pub trash: Vec<TrashedWorkspace>,
+    /// The workspace defined by a `.workspace.toml` above the current directory, usable without
+    /// being registered
+    pub local_workspace: Option<Workspace>,
+    pub store: Rc<dyn WorkspaceStore>,
+    /// Path prefixes rewritten on this machine
+    pub remaps: Vec<Remap>,
+    /// Where mutations are recorded so they can be undone, if anywhere
+    pub journal: Option<Journal>,
+    /// Included workspaces defined with the same name in two files, the first of which is used
+    pub conflicts: Vec<(Workspace, Workspace)>,
 }
 
 impl Workspaces {
-    pub fn new(file_path: &str) -> Workspaces {
-        // If file doesn't exist, create it
-        if !Path::new(file_path).exists() {
-            fs::File::create(file_path).expect("Unable to create file");
+    pub fn new<P: AsRef<Path>>(file_path: P) -> Result<Workspaces, WorkspaceError> {
+        read_from_file(file_path)
+    }
+
+    /// Loads the workspaces from any storage backend
+    pub fn with_store<S: WorkspaceStore + 'static>(store: S) -> Result<Workspaces, WorkspaceError> {
+        let registry = store.load()?;
+
+        Ok(Workspaces {
+            active_workspace: find_active(&registry.workspaces, &[]),
+            workspaces: registry.workspaces,
+            included: Vec::new(),
+            trash: registry.trash,
+            local_workspace: None,
+            store: Rc::new(store),
+            remaps: Vec::new(),
+            journal: None,
+            conflicts: Vec::new(),
+        })
+    }
+
+    /// Loads only the registered workspace called `name`, for commands acting on one workspace.
+    /// The lookup is an indexed query with backends that have one, and the full list is read
+    /// back after the first change.
+    pub fn with_named<S: WorkspaceStore + 'static>(
+        store: S,
+        name: &str,
+    ) -> Result<Workspaces, WorkspaceError> {
+        let workspaces: Vec<Workspace> = store.find_by_name(name)?.into_iter().collect();
+
+        Ok(Workspaces {
+            active_workspace: find_active(&workspaces, &[]),
+            workspaces,
+            included: Vec::new(),
+            trash: Vec::new(),
+            local_workspace: None,
+            store: Rc::new(store),
+            remaps: Vec::new(),
+            journal: None,
+            conflicts: Vec::new(),
+        })
+    }
+
+    /// Merges the workspaces of read-only registry files into the list. Registered workspaces
+    /// override included ones with the same name. The same name in two included files is a
+    /// conflict, reported once that workspace is used unless it is overridden.
+    pub fn include<P: AsRef<Path>>(&mut self, files: &[P]) -> Result<(), WorkspaceError> {
+        let mut included: Vec<Workspace> = Vec::new();
+
+        for file in files {
+            let file = file.as_ref();
+            let contents = fs::read_to_string(file).map_err(|e| {
+                format!("Could not read included registry {}: {}", file.display(), e)
+            })?;
+            let registry = Registry::parse(&contents).map_err(|e| {
+                format!(
+                    "Could not parse included registry {}: {}",
+                    file.display(),
+                    e
+                )
+            })?;
+
+            for mut workspace in registry.workspaces {
+                workspace.id = String::new();
+                workspace.source = WorkspaceSource::Include(file.to_path_buf());
+                included.push(workspace);
+            }
         }
 
-        read_from_file(file_path)
+        self.conflicts.clear();
+        for (index, workspace) in included.iter().enumerate() {
+            if let Some(other) = included[..index]
+                .iter()
+                .find(|w| w.name == workspace.name && w.source != workspace.source)
+            {
+                self.conflicts.push((other.clone(), workspace.clone()));
+            }
+        }
+
+        self.included = included;
+        let registered = self.registered();
+        self.merge(registered);
+
+        if self.active_workspace.is_none() {
+            self.active_workspace = find_active(&self.workspaces, &self.remaps);
+        }
+        Ok(())
+    }
+
+    /// The error for using `name` while two included files define it and it isn't overridden
+    pub fn conflict(&self, name: &str) -> Option<WorkspaceError> {
+        if self.is_registered(name) {
+            return None;
+        }
+        let (first, other) = self
+            .conflicts
+            .iter()
+            .find(|(first, _)| first.name == name)?;
+        Some(WorkspaceError::coded(
+            ErrorCode::Ambiguous,
+            format!(
+                "Workspace {} is defined in both {} and {}, add it to your registry with `ws add` to choose one",
+                name,
+                self.source_location(first),
+                self.source_location(other)
+            ),
+        ))
+    }
+
+    /// Sets the path remaps of this machine, which apply to every workspace path resolved from
+    /// now on
+    pub fn set_remaps(&mut self, remaps: Vec<Remap>) {
+        self.remaps = remaps;
+        self.active_workspace =
+            find_active(&self.workspaces, &self.remaps).or_else(|| self.local_workspace.clone());
     }
 
-    pub fn add(&mut self, workspace: Workspace) {
-        self.add_to_file(&workspace);
-        self.workspaces.push(workspace);
+    /// The path a workspace refers to on this machine: its stored path with placeholders
+    /// expanded and the remaps applied
+    pub fn resolve(&self, workspace: &Workspace) -> Result<PathBuf, WorkspaceError> {
+        resolve(workspace, &self.remaps)
     }
 
-    pub fn init(&self) {
-        for workspace in &self.workspaces {
-            workspace.init();
+    /// Names the file a workspace was loaded from
+    pub fn source_location(&self, workspace: &Workspace) -> String {
+        match &workspace.source {
+            WorkspaceSource::Registry => self.store.location(),
+            WorkspaceSource::Include(path) | WorkspaceSource::Local(path) => {
+                path.display().to_string()
+            }
         }
     }
 
-    pub fn add_to_file(&self, workspace: &Workspace) {
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .open(self.workspace_file.as_str())
-            .expect("Unable to open file");
+    fn is_registered(&self, name: &str) -> bool {
+        self.workspaces
+            .iter()
+            .any(|w| w.name == name && w.source == WorkspaceSource::Registry)
+    }
 
-        if self
-            .workspaces
+    fn registered(&self) -> Vec<Workspace> {
+        self.workspaces
             .iter()
-            .any(|w| w.name == workspace.name && w.path == workspace.path)
-        {
-            eprintln!("Workspace already exists");
-            return;
+            .filter(|w| w.source == WorkspaceSource::Registry)
+            .cloned()
+            .collect()
+    }
+
+    fn merge(&mut self, registered: Vec<Workspace>) {
+        let not_overridden: Vec<Workspace> = self
+            .included
+            .iter()
+            .filter(|w| !registered.iter().any(|r| r.name == w.name))
+            .cloned()
+            .collect();
+
+        self.workspaces = registered;
+        for workspace in not_overridden {
+            if !self.workspaces.iter().any(|w| w.name == workspace.name) {
+                self.workspaces.push(workspace);
+            }
         }
+    }
+
+    /// Looks for a `.workspace.toml` from `dir` upwards and makes its workspace available
+    pub fn discover_local(&mut self, dir: &Path) -> Result<(), WorkspaceError> {
+        self.local_workspace = local::discover(dir)?;
 
-        if let Err(e) = writeln!(
-            file,
-            "{};{};{};{}",
-            workspace.name,
-            workspace.path.display(),
-            workspace.shell,
-            workspace.init_commands.join(";")
-        ) {
-            eprintln!("Couldn't write to file: {}", e);
+        if self.active_workspace.is_none() {
+            self.active_workspace = self.local_workspace.clone();
         }
+        Ok(())
     }
 
-    pub fn clear(&mut self) {
-        self.workspaces.clear();
-        self.active_workspace = None;
+    /// Finds a workspace by name, preferring registered workspaces over the local definition
+    pub fn find(&self, name: &str) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .chain(self.local_workspace.iter())
+            .find(|w| w.name == name)
+    }
 
-        fs::write(self.workspace_file.as_str(), "").expect("Unable to clear file");
+    /// The listed workspaces followed by the local one, unless it is also registered
+    pub fn with_local(&self) -> impl Iterator<Item = &Workspace> {
+        self.workspaces.iter().chain(
+            self.local_workspace
+                .iter()
+                .filter(|local| !self.workspaces.contains(local)),
+        )
     }
 
-    pub fn remove_from_file(&mut self, workspace: &Workspace) {
-        let mut file =
-            fs::File::create(self.workspace_file.as_str()).expect("Unable to create file");
+    /// Registers a workspace under a new ID. Names are unique within the registry.
+    pub fn add(&mut self, workspace: Workspace) -> Result<(), WorkspaceError> {
+        self.update(format!("add {}", workspace.name), |registry| {
+            if registry.workspaces.iter().any(|w| w.name == workspace.name) {
+                return Err(already_exists(&workspace.name));
+            }
 
-        self.workspaces
-            .retain(|w| w.name != workspace.name && w.path != workspace.path);
+            registry.workspaces.push(Workspace {
+                id: String::new(),
+                created_at: Some(timestamps::now()),
+                last_used_at: None,
+                launch_count: 0,
+                ..workspace.clone()
+            });
+            registry.assign_ids();
+            Ok(())
+        })
+    }
 
-        for workspace in &self.workspaces {
-            if let Err(e) = writeln!(
-                file,
-                "{};{};{}",
+    /// Adds every workspace of `registry` whose name isn't registered yet, returning how many
+    /// were added
+    pub fn import(&mut self, registry: &Registry) -> Result<usize, WorkspaceError> {
+        let mut imported = 0;
+        self.update("import".to_string(), |current| {
+            imported = 0;
+            for workspace in &registry.workspaces {
+                if !current.workspaces.iter().any(|w| w.name == workspace.name) {
+                    // IDs are only unique within their own registry
+                    current.workspaces.push(Workspace {
+                        id: String::new(),
+                        created_at: workspace.created_at.or(Some(timestamps::now())),
+                        ..workspace.clone()
+                    });
+                    imported += 1;
+                }
+            }
+            current.assign_ids();
+            Ok(())
+        })?;
+        Ok(imported)
+    }
+
+    /// Replaces the registered workspace with the ID `id`, keeping the ID and the usage
+    pub fn replace(&mut self, id: &str, workspace: Workspace) -> Result<(), WorkspaceError> {
+        self.update(format!("modify {}", workspace.name), |registry| {
+            if registry
+                .workspaces
+                .iter()
+                .any(|w| w.name == workspace.name && w.id != id)
+            {
+                return Err(already_exists(&workspace.name));
+            }
+
+            match registry.workspaces.iter_mut().find(|w| w.id == id) {
+                Some(stored) => {
+                    *stored = Workspace {
+                        id: id.to_string(),
+                        created_at: stored.created_at,
+                        last_used_at: stored.last_used_at,
+                        launch_count: stored.launch_count,
+                        ..workspace.clone()
+                    };
+                    Ok(())
+                }
+                None => Err(WorkspaceError::coded(
+                    ErrorCode::NotFound,
+                    "Workspace not found".to_string(),
+                )),
+            }
+        })
+    }
+
+    /// Renames a registered workspace, keeping its ID and usage. Included and local workspaces
+    /// can't be changed, so like a modify or edit this registers a copy under the new name.
+    pub fn rename(&mut self, workspace: &Workspace, new_name: &str) -> Result<(), WorkspaceError> {
+        if workspace.source != WorkspaceSource::Registry {
+            return self.add(Workspace {
+                id: String::new(),
+                name: new_name.to_string(),
+                source: WorkspaceSource::Registry,
+                ..workspace.clone()
+            });
+        }
+
+        let description = format!("rename {} to {}", workspace.name, new_name);
+        self.update(description, |registry| {
+            if registry.workspaces.iter().any(|w| w.name == new_name) {
+                return Err(already_exists(new_name));
+            }
+            match registry
+                .workspaces
+                .iter_mut()
+                .find(|w| w.id == workspace.id)
+            {
+                Some(stored) => {
+                    stored.name = new_name.to_string();
+                    Ok(())
+                }
+                None => Err(WorkspaceError::coded(
+                    ErrorCode::NotFound,
+                    "Workspace not found".to_string(),
+                )),
+            }
+        })
+    }
+
+    /// Applies `patch` to the registered workspace with the ID `id` in a single update
+    pub fn patch(&mut self, id: &str, patch: &Patch) -> Result<(), WorkspaceError> {
+        let name = self
+            .workspaces
+            .iter()
+            .find(|w| w.id == id)
+            .map_or_else(|| id.to_string(), |w| w.name.clone());
+
+        self.update(format!("modify {}", name), |registry| {
+            match registry.workspaces.iter_mut().find(|w| w.id == id) {
+                Some(stored) => patch.apply(stored),
+                None => Err(WorkspaceError::coded(
+                    ErrorCode::NotFound,
+                    "Workspace not found".to_string(),
+                )),
+            }
+        })
+    }
+
+    /// Records that a registered workspace was initialized. Usage isn't journaled, as there is
+    /// nothing worth undoing about it.
+    pub fn record_launch(&mut self, workspace: &Workspace) -> Result<(), WorkspaceError> {
+        if workspace.source != WorkspaceSource::Registry || workspace.id.is_empty() {
+            return Ok(());
+        }
+
+        let updated =
+            self.store
+                .update_workspace(&workspace.name, &workspace.id, &mut |stored| {
+                    stored.last_used_at = Some(timestamps::now());
+                    stored.launch_count += 1;
+                })?;
+        if let Some(updated) = updated {
+            if let Some(listed) = self.workspaces.iter_mut().find(|w| w.id == updated.id) {
+                *listed = updated;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the init commands of every workspace with all of `tags`, without opening shells,
+    /// returning the workspaces brought up
+    pub fn up(&mut self, tags: &[String]) -> Result<Vec<Workspace>, WorkspaceError> {
+        let tagged: Vec<Workspace> = self
+            .with_local()
+            .filter(|w| w.has_tags(tags))
+            .cloned()
+            .collect();
+
+        for workspace in &tagged {
+            let path = self.resolve(workspace)?;
+            say!(
+                "Bringing up workspace {} at {}",
                 workspace.name,
-                workspace.path.display(),
-                workspace.init_commands.join(";")
-            ) {
-                eprintln!("Couldn't write to file: {}", e);
+                path.display()
+            );
+            // Failing to record usage shouldn't keep the workspace from coming up
+            if let Err(err) = self.record_launch(workspace) {
+                output::warn(err);
             }
+            workspace.run_init_commands()?;
         }
+        Ok(tagged)
     }
-}
 
-impl Clone for Workspaces {
-    fn clone(&self) -> Self {
-        Workspaces {
-            active_workspace: self.active_workspace.clone(),
-            workspaces: self.workspaces.clone(),
-            workspace_file: self.workspace_file.clone(),
+    /// Moves every registered workspace to the trash, failing without a change if there are
+    /// none
+    pub fn clear(&mut self) -> Result<(), WorkspaceError> {
+        self.update("clear".to_string(), |registry| {
+            if registry.workspaces.is_empty() {
+                return Err(WorkspaceError::coded(
+                    ErrorCode::Empty,
+                    "No registered workspaces to delete".to_string(),
+                ));
+            }
+            let deleted_at = timestamps::now();
+            for workspace in registry.workspaces.drain(..) {
+                registry.trash.push(TrashedWorkspace {
+                    deleted_at,
+                    workspace,
+                });
+            }
+            Ok(())
+        })
+    }
+
+    /// Moves a workspace to the trash
+    pub fn remove_from_file(&mut self, workspace: &Workspace) -> Result<(), WorkspaceError> {
+        if workspace.source != WorkspaceSource::Registry {
+            return Err(read_only_error(self, workspace));
         }
+        if workspace.id.is_empty() {
+            return Err(format!("Workspace {} is not registered", workspace.name).into());
+        }
+
+        self.update(format!("delete {}", workspace.name), |registry| {
+            let deleted_at = timestamps::now();
+            let (kept, deleted) = registry
+                .workspaces
+                .drain(..)
+                .partition(|w| w.id != workspace.id);
+            registry.workspaces = kept;
+            registry
+                .trash
+                .extend(deleted.into_iter().map(|workspace| TrashedWorkspace {
+                    deleted_at,
+                    workspace,
+                }));
+            Ok(())
+        })?;
+        self.active_workspace = None;
+        Ok(())
+    }
+
+    /// Moves every registered workspace with all of `tags` to the trash in a single change,
+    /// returning them
+    pub fn remove_tagged(&mut self, tags: &[String]) -> Result<Vec<Workspace>, WorkspaceError> {
+        let mut removed = Vec::new();
+
+        self.update(format!("delete tagged {}", tags.join(", ")), |registry| {
+            let deleted_at = timestamps::now();
+            let (deleted, kept): (Vec<Workspace>, _) = registry
+                .workspaces
+                .drain(..)
+                .partition(|w| w.has_tags(tags));
+            registry.workspaces = kept;
+            registry
+                .trash
+                .extend(deleted.iter().map(|workspace| TrashedWorkspace {
+                    deleted_at,
+                    workspace: workspace.clone(),
+                }));
+            removed = deleted;
+            Ok(())
+        })?;
+        Ok(removed)
     }
+
+    /// Moves the most recently deleted workspace called `name` out of the trash
+    pub fn restore(&mut self, name: &str) -> Result<(), WorkspaceError> {
+        self.update(format!("restore {}", name), |registry| {
+            let index = match registry
+                .trash
+                .iter()
+                .rposition(|trashed| trashed.workspace.name == name)
+            {
+                Some(index) => index,
+                None => {
+                    return Err(WorkspaceError::coded(
+                        ErrorCode::NotFound,
+                        format!("Workspace {} is not in the trash", name),
+                    ))
+                }
+            };
+
+            if registry.workspaces.iter().any(|w| w.name == name) {
+                return Err(already_exists(name));
+            }
+
+            let trashed = registry.trash.remove(index);
+            registry.workspaces.push(trashed.workspace);
+            Ok(())
+        })
+    }
+
+    /// Permanently deletes the trashed workspaces deleted more than `older_than` seconds ago,
+    /// or all of them, returning the purged entries
+    pub fn purge(
+        &mut self,
+        older_than: Option<u64>,
+    ) -> Result<Vec<TrashedWorkspace>, WorkspaceError> {
+        let cutoff = older_than.map(|age| timestamps::now().saturating_sub(age));
+        let mut purged = Vec::new();
+
+        self.update("purge trash".to_string(), |registry| {
+            let (kept, removed) = registry
+                .trash
+                .drain(..)
+                .partition(|trashed| cutoff.is_some_and(|cutoff| trashed.deleted_at > cutoff));
+            registry.trash = kept;
+            purged = removed;
+            Ok(())
+        })?;
+        Ok(purged)
+    }
+
+    /// Reverts the last `count` changes that haven't been undone yet, returning them. Changes
+    /// made since then by anything else are kept.
+    pub fn undo(&mut self, count: usize) -> Result<Vec<JournalEntry>, WorkspaceError> {
+        let journal = match &self.journal {
+            Some(journal) => journal.clone(),
+            None => return Err("No journal is kept for this registry".to_string().into()),
+        };
+        let lock = journal.lock()?;
+
+        let mut undoable = lock.undoable()?;
+        if count == 0 || count > undoable.len() {
+            return Err(WorkspaceError::coded(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Cannot undo {} changes, {} can be undone",
+                    count,
+                    undoable.len()
+                ),
+            ));
+        }
+
+        let reverted = undoable.split_off(undoable.len() - count);
+        let sequences = reverted.iter().map(|entry| entry.sequence).collect();
+        self.journaled_update(
+            Some(lock),
+            format!("undo {}", count),
+            sequences,
+            |registry| {
+                for entry in reverted.iter().rev() {
+                    entry.revert(registry);
+                }
+                // Snapshots taken before version 2 have no IDs
+                registry.assign_ids();
+                Ok(())
+            },
+        )?;
+        Ok(reverted)
+    }
+
+    /// Applies `modify` to the stored registry, records it in the journal and refreshes the
+    /// in-memory list from the result
+    fn update<F>(&mut self, description: String, modify: F) -> Result<(), WorkspaceError>
+    where
+        F: FnMut(&mut Registry) -> Result<(), WorkspaceError>,
+    {
+        let journal = self.journal.clone();
+        // The journal is locked before the registry, so concurrent runs journal their changes
+        // in the order they were made. A broken journal shouldn't keep the registry from
+        // changing.
+        let lock = journal.as_ref().and_then(|journal| {
+            journal
+                .lock()
+                .map_err(|err| output::warn(format!("{}, the change can't be undone", err)))
+                .ok()
+        });
+        self.journaled_update(lock, description, Vec::new(), modify)
+    }
+
+    fn journaled_update<F>(
+        &mut self,
+        lock: Option<JournalLock>,
+        description: String,
+        reverts: Vec<u64>,
+        mut modify: F,
+    ) -> Result<(), WorkspaceError>
+    where
+        F: FnMut(&mut Registry) -> Result<(), WorkspaceError>,
+    {
+        let mut before = Registry::default();
+        let registry = self.store.update(&mut |registry| {
+            before = registry.clone();
+            modify(registry)
+        })?;
+
+        // The change is made, so failing to journal it is only worth a warning
+        if let Some(lock) = lock {
+            if let Err(err) = lock.append(description, &before, &registry, reverts) {
+                output::warn(format!("{}, the change can't be undone", err));
+            }
+        }
+        self.trash = registry.trash;
+        self.merge(registry.workspaces);
+        Ok(())
+    }
+}
+
+/// Tags are single words, so they can be given as comma-separated lists
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.contains(|c: char| c == ',' || c.is_whitespace())
 }
 
-pub fn read_from_file(file_path: &str) -> Workspaces {
-    let contents = fs::read_to_string(file_path).expect("Something went wrong reading the file");
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
 
-    let mut workspaces = Vec::new();
-    let mut active_workspace = None;
+fn already_exists(name: &str) -> WorkspaceError {
+    WorkspaceError::coded(
+        ErrorCode::AlreadyExists,
+        format!("Workspace {} already exists", name),
+    )
+}
 
-    for line in contents.lines() {
-        let mut parts = line.split(";");
-        let name = parts.next().unwrap();
-        let path = parts.next().unwrap();
-        let shell = parts.next().unwrap();
-        let init_commands = parts.map(|s| s.to_string()).collect();
+fn read_only_error(workspaces: &Workspaces, workspace: &Workspace) -> WorkspaceError {
+    let location = workspaces.source_location(workspace);
+    let message = match workspace.source {
+        WorkspaceSource::Local(_) => format!(
+            "Workspace {} is defined in {} and is read-only, edit that file or add a workspace with the same name to override it",
+            workspace.name, location
+        ),
+        _ => format!(
+            "Workspace {} comes from the included registry {} and is read-only, add a workspace with the same name to override it",
+            workspace.name, location
+        ),
+    };
+    WorkspaceError::coded(ErrorCode::ReadOnly, message)
+}
 
-        let workspace = Workspace::new(
-            name.to_string(),
-            path.to_string().into(),
-            shell.to_string(),
-            init_commands,
-        );
+fn resolve(workspace: &Workspace, remaps: &[Remap]) -> Result<PathBuf, WorkspaceError> {
+    Ok(paths::remap(&workspace.expanded_path()?, remaps))
+}
+
+/// The last workspace containing the current directory
+fn find_active(workspaces: &[Workspace], remaps: &[Remap]) -> Option<Workspace> {
+    let mut active_workspace = None;
 
+    for workspace in workspaces {
         // If the current path is equal to the workspace path or is a subfolder, set it as active
-        if let Ok(current_path) = env::current_dir() {
-            if current_path.starts_with(&workspace.path) {
+        if let (Ok(current_path), Ok(path)) = (env::current_dir(), resolve(workspace, remaps)) {
+            if current_path.starts_with(path) {
                 active_workspace = Some(workspace.clone());
             }
         }
-        workspaces.push(workspace);
     }
+    active_workspace
+}
 
-    Workspaces {
-        active_workspace,
-        workspaces,
-        workspace_file: file_path.to_string(),
+impl Clone for Workspaces {
+    fn clone(&self) -> Self {
+        Workspaces {
+            active_workspace: self.active_workspace.clone(),
+            workspaces: self.workspaces.clone(),
+            included: self.included.clone(),
+            trash: self.trash.clone(),
+            local_workspace: self.local_workspace.clone(),
+            store: Rc::clone(&self.store),
+            remaps: self.remaps.clone(),
+            journal: self.journal.clone(),
+            conflicts: self.conflicts.clone(),
+        }
     }
 }
+
+pub fn read_from_file<P: AsRef<Path>>(file_path: P) -> Result<Workspaces, WorkspaceError> {
+    Workspaces::with_store(FileStore::new(file_path))
+}